@@ -14,5 +14,5 @@ class MyClass:
     def get_value(self):
         return self.value
 mything = MyClass(69)
-value = mything
-print("The value is: " + str(value.value))
+value = mything.get_value()
+print("The value is: " + str(value))
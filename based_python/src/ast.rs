@@ -1,90 +1,431 @@
 #[derive(Debug)]
 pub enum Statement {
+    /// `a = b = value`, with one target per `=`.
     Assignment {
-        name: String,
+        targets: Vec<Target>,
+        value: Expression,
+    },
+    /// `x += value`
+    AugmentedAssignment {
+        target: Target,
+        operator: Operator,
         value: Expression,
     },
-    Print {
-        content: Expression,
+    /// `x: int = value`, or `x: int` without a value.
+    AnnotatedAssignment {
+        target: Target,
+        annotation: Expression,
+        value: Option<Expression>,
     },
     Return {
-        value: Expression,
+        value: Option<Expression>,
     },
     If {
         condition: Expression,
         consequence: Block,
+        elif_clauses: Vec<ElifClause>,
         alternative: Option<Block>,
     },
     For {
-        iterator: Expression,
+        /// `async for`, over an asynchronous iterable.
+        is_async: bool,
+        target: Target,
+        iterable: Expression,
         body: Block,
+        alternative: Option<Block>,
     },
     While {
         condition: Expression,
         body: Block,
     },
     FunctionDef {
+        decorators: Vec<Spanned<Expression>>,
+        /// `async fn`, a coroutine function.
+        is_async: bool,
         name: String,
-        args: Vec<String>,
+        parameters: Vec<Parameter>,
+        returns: Option<Expression>,
         body: Block,
     },
-    FunctionCall {
-        name: String,
-        arguments: Vec<Expression>
-    },
     ClassDef {
+        decorators: Vec<Spanned<Expression>>,
         name: String,
+        /// Base classes and class keywords, such as `metaclass=Meta`.
+        arguments: Vec<Argument>,
+        body: Block,
+    },
+    With {
+        /// `async with`, using asynchronous context managers.
+        is_async: bool,
+        items: Vec<WithItem>,
+        body: Block,
+    },
+    Match {
+        subject: Expression,
+        cases: Vec<MatchCase>,
+    },
+    Try {
         body: Block,
+        handlers: Vec<ExceptHandler>,
+        /// `except*` handlers, which match exceptions inside an exception group.
+        is_group: bool,
+        alternative: Option<Block>,
+        finally: Option<Block>,
+    },
+    /// `import a.b as c, d`
+    Import {
+        names: Vec<ImportAlias>,
+    },
+    /// `from ..a import b as c`
+    FromImport {
+        /// The module path after the leading dots, if any.
+        module: Option<String>,
+        /// The number of leading dots; 0 for an absolute import.
+        level: usize,
+        /// `None` for `from m import *`.
+        names: Option<Vec<ImportAlias>>,
+    },
+    Raise {
+        /// `None` for a bare `raise`, which re-raises the active exception.
+        exception: Option<Expression>,
+        cause: Option<Expression>,
+    },
+    Break,
+    Continue,
+    Pass,
+    /// `del a, b[0], c.d`
+    Delete {
+        targets: Vec<Target>,
+    },
+    Assert {
+        test: Expression,
+        message: Option<Expression>,
+    },
+    Global {
+        names: Vec<String>,
+    },
+    Nonlocal {
+        names: Vec<String>,
+    },
+    /// An expression evaluated for its side effects, usually a call.
+    Expression(Expression),
+}
+
+#[derive(Debug)]
+pub enum FStringPart {
+    /// Literal text as written, with `{{`/`}}` and escapes left untouched.
+    Literal(String),
+    Replacement {
+        expression: Box<Expression>,
+        /// For `{a + b = }`, the text after the `{` up to the conversion or
+        /// format spec, `a + b = `, which Python prints before the value.
+        debug: Option<String>,
+        /// `r`, `s` or `a` from `{x!r}`
+        conversion: Option<char>,
+        format_spec: Vec<FStringPart>,
+        /// The braces and everything between them.
+        span: Span,
     },
 }
 
+/// One `for` clause of a comprehension, with the `if` conditions after it.
+#[derive(Debug)]
+pub struct Comprehension {
+    pub target: Target,
+    pub iterable: Expression,
+    pub conditions: Vec<Expression>,
+}
+
+#[derive(Debug)]
+pub enum LambdaBody {
+    Expression(Box<Expression>),
+    /// The braced body of an arrow function, lifted into a `def` by codegen.
+    Block(Block),
+}
+
+/// An argument at a call site.
+#[derive(Debug)]
+pub enum Argument {
+    Positional(Expression),
+    /// `name=value`
+    Keyword {
+        name: String,
+        value: Expression,
+    },
+    /// `*iterable`
+    Unpack(Expression),
+    /// `**mapping`
+    UnpackKeywords(Expression),
+}
+
+/// A parameter in a function definition. The `/` and `*` markers are not
+/// stored; they are implied by the parameters' kinds.
+#[derive(Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub kind: ParameterKind,
+    pub annotation: Option<Expression>,
+    pub default: Option<Expression>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    /// Before a `/`.
+    PositionalOnly,
+    PositionalOrKeyword,
+    /// After a `*` or `*args`.
+    KeywordOnly,
+    /// `*args`
+    VarPositional,
+    /// `**kwargs`
+    VarKeyword,
+}
+
+/// A name, or a pattern of names, that a value is bound to.
+#[derive(Debug)]
+pub enum Target {
+    Identifier(String),
+    Attribute {
+        object: Box<Expression>,
+        member: String,
+    },
+    Subscript {
+        value: Box<Expression>,
+        index: Box<Expression>,
+    },
+    Tuple(Vec<Target>),
+    Starred(Box<Target>),
+}
+
 #[derive(Debug)]
 pub enum Expression {
     Identifier(String),
-    Number(f64),
+    /// Numeric literals hold their source text, e.g. `0xFF`, `1_000` or `1e100`.
+    Integer(String),
+    Float(String),
+    Imaginary(String),
+    /// A string literal exactly as written, prefix and quotes included.
     String(String),
+    FormattedString {
+        prefix: String,
+        quote: String,
+        parts: Vec<FStringPart>,
+    },
     BinaryOp {
         left: Box<Expression>,
-        operator: String,
+        operator: Operator,
         right: Box<Expression>,
     },
+    UnaryOp {
+        operator: UnaryOperator,
+        operand: Box<Expression>,
+    },
     MemberAccess {
         object: Box<Expression>,
         member: String,
     },
-    FunctionCall {
+    Call {
+        function: Box<Expression>,
+        arguments: Vec<Argument>,
+    },
+    List(Vec<Expression>),
+    Tuple(Vec<Expression>),
+    Set(Vec<Expression>),
+    Dict(Vec<(Expression, Expression)>),
+    Subscript {
+        value: Box<Expression>,
+        index: Box<Expression>,
+    },
+    ListComprehension {
+        element: Box<Expression>,
+        generators: Vec<Comprehension>,
+    },
+    SetComprehension {
+        element: Box<Expression>,
+        generators: Vec<Comprehension>,
+    },
+    DictComprehension {
+        key: Box<Expression>,
+        value: Box<Expression>,
+        generators: Vec<Comprehension>,
+    },
+    Generator {
+        element: Box<Expression>,
+        generators: Vec<Comprehension>,
+    },
+    Yield {
+        value: Option<Box<Expression>>,
+    },
+    YieldFrom {
+        value: Box<Expression>,
+    },
+    Await {
+        value: Box<Expression>,
+    },
+    /// `a < b <= c`: a chain of comparisons, each sharing its left operand
+    /// with the previous one, as in Python.
+    Compare {
+        left: Box<Expression>,
+        comparisons: Vec<(Operator, Expression)>,
+    },
+    /// `body if test else orelse`, or `test ? body : orelse`.
+    Conditional {
+        test: Box<Expression>,
+        body: Box<Expression>,
+        orelse: Box<Expression>,
+    },
+    /// `name := value`
+    Walrus {
         name: String,
-        args: Vec<Expression>,
+        value: Box<Expression>,
+    },
+    /// A `lambda` or an arrow function.
+    Lambda {
+        parameters: Vec<Parameter>,
+        body: LambdaBody,
+    },
+    /// `lower:upper:step`; only valid as (part of) a subscript index.
+    Slice {
+        lower: Option<Box<Expression>>,
+        upper: Option<Box<Expression>>,
+        step: Option<Box<Expression>>,
     },
-    ClassInstantiation {
-        class_name: String,
-        arguments: Vec<Expression>
-    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operator {
     Add,
     Sub,
     Mul,
     Div,
+    FloorDiv,
+    Mod,
+    Pow,
+    MatMul,
+    LShift,
+    RShift,
+    BitAnd,
+    BitOr,
+    BitXor,
     Eq,
     NotEq,
     Lt,
     Gt,
     LtEq,
     GtEq,
+    In,
+    NotIn,
+    Is,
+    IsNot,
     And,
     Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
     Not,
+    Neg,
+    Pos,
+    Invert,
+}
+
+/// An `else if` branch of an `if` statement.
+#[derive(Debug)]
+pub struct ElifClause {
+    pub condition: Expression,
+    pub consequence: Block,
+    pub span: Span,
+}
+
+/// A context manager in a `with` statement: `open(p) as f`.
+#[derive(Debug)]
+pub struct WithItem {
+    pub context: Expression,
+    pub target: Option<Target>,
+}
+
+/// An `except` branch of a `try` statement.
+#[derive(Debug)]
+pub struct ExceptHandler {
+    /// The exception types caught; empty for a bare `except`.
+    pub types: Vec<Expression>,
+    pub name: Option<String>,
+    pub body: Block,
+    pub span: Span,
+}
+
+/// A `case` branch of a `match` statement.
+#[derive(Debug)]
+pub struct MatchCase {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: Block,
+    pub span: Span,
+}
+
+/// A pattern in a `case`. Parenthesised groups are not kept, and `(a, b)` is
+/// the same sequence pattern as `[a, b]`.
+#[derive(Debug)]
+pub enum Pattern {
+    /// A number, string, or `None`/`True`/`False`.
+    Literal(Expression),
+    /// A bare name, which matches anything and binds it.
+    Capture(String),
+    /// `_`
+    Wildcard,
+    /// A dotted name such as `Color.RED`, compared by equality.
+    Value(Expression),
+    Sequence(Vec<Pattern>),
+    /// `*rest` inside a sequence pattern; `None` for `*_`.
+    Star(Option<String>),
+    Mapping {
+        /// Literal or value keys, each with the pattern for its value.
+        entries: Vec<(Expression, Pattern)>,
+        /// `**rest`
+        rest: Option<String>,
+    },
+    /// `Point(0, y=y)`
+    Class {
+        class: Expression,
+        positional: Vec<Pattern>,
+        keywords: Vec<(String, Pattern)>,
+    },
+    /// `a | b`
+    Or(Vec<Pattern>),
+    /// `pattern as name`
+    As {
+        pattern: Box<Pattern>,
+        name: String,
+    },
+}
+
+/// A name in an import statement, with its `as` alias.
+#[derive(Debug)]
+pub struct ImportAlias {
+    /// A dotted module path for `import`, a plain name for `from ... import`.
+    pub name: String,
+    pub alias: Option<String>,
+}
+
+/// A byte range in the source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub struct Block {
-    pub statements: Vec<Statement>,
+    pub statements: Vec<Spanned<Statement>>,
 }
 
 #[derive(Debug)]
 pub struct Program {
-    pub statements: Vec<Statement>,
+    pub statements: Vec<Spanned<Statement>>,
 }
\ No newline at end of file
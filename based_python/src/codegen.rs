@@ -1,95 +1,1670 @@
-use crate::ast::{Program, Statement, Expression, Block};
+use std::fmt;
+use std::str::FromStr;
+use crate::ast::{Program, Statement, Expression, Argument, Block, Comprehension, FStringPart, ImportAlias, LambdaBody, MatchCase, Operator, Parameter, ParameterKind, Pattern, Span, Spanned, Target, UnaryOperator};
+use crate::diagnostic::{tokens, Diagnostic, SourceFile};
 
-pub fn generate_python_code(program: &Program) -> String {
-    let mut output = String::new();
+/// Python keywords that Bython accepts as identifiers but Python would reject.
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break",
+    "class", "continue", "def", "del", "elif", "else", "except", "finally", "for",
+    "from", "global", "if", "import", "in", "is", "lambda", "nonlocal", "not", "or",
+    "pass", "raise", "return", "try", "while", "with", "yield",
+];
+
+/// Constants that are keywords in Python but still valid in expression position.
+const PYTHON_CONSTANTS: &[&str] = &["False", "None", "True"];
+
+/// Built-in classes whose single positional sub-pattern matches the subject
+/// itself: `case int(n)`.
+const SELF_MATCHING_CLASSES: &[&str] = &[
+    "bool", "bytearray", "bytes", "dict", "float", "frozenset", "int", "list", "set", "str", "tuple",
+];
+
+#[derive(Debug)]
+struct CodegenError {
+    message: String,
+    span: Span,
+    /// The name the error is about. The diagnostic points at it within `span`
+    /// rather than at the whole statement.
+    name: Option<String>,
+}
+
+impl CodegenError {
+    fn into_diagnostic(self, source: &SourceFile) -> Diagnostic {
+        let span = self.name
+            .and_then(|name| source.find_token(self.span, &name))
+            .unwrap_or(self.span);
+        Diagnostic::new(source, span, self.message)
+    }
+}
+
+/// Maps each line of generated Python back to the Bython statement it came from.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    lines: Vec<Span>,
+}
+
+impl SourceMap {
+    /// Returns the source span for a 1-based line of generated Python.
+    pub fn lookup(&self, python_line: usize) -> Option<Span> {
+        python_line.checked_sub(1).and_then(|i| self.lines.get(i)).copied()
+    }
+}
+
+#[derive(Debug)]
+pub struct GeneratedCode {
+    pub python: String,
+    pub source_map: SourceMap,
+}
+
+/// The Python version that generated code has to run on, such as `3.9`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PythonVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl PythonVersion {
+    /// The oldest version we generate code for: lowered constructs rely on `:=`.
+    pub const OLDEST: PythonVersion = PythonVersion { major: 3, minor: 8 };
+    /// The first version where a decorator can be any expression, not just a
+    /// dotted name with an optional call.
+    const DECORATOR_EXPRESSIONS: PythonVersion = PythonVersion { major: 3, minor: 9 };
+    /// The first version with `match` statements.
+    const MATCH: PythonVersion = PythonVersion { major: 3, minor: 10 };
+    /// The first version with `except*`.
+    const EXCEPT_GROUPS: PythonVersion = PythonVersion { major: 3, minor: 11 };
+    /// The first version where f-string replacement fields may reuse the
+    /// string's quotes, or contain backslashes and `#`.
+    const FSTRING_EXPRESSIONS: PythonVersion = PythonVersion { major: 3, minor: 12 };
+}
+
+impl Default for PythonVersion {
+    fn default() -> Self {
+        PythonVersion { major: 3, minor: 12 }
+    }
+}
+
+impl fmt::Display for PythonVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl FromStr for PythonVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |part: &str| part.parse::<u32>().ok();
+        let version = match s.split_once('.') {
+            Some((major, minor)) => parse(major).zip(parse(minor)),
+            None => None,
+        };
+        match version {
+            Some((major, minor)) if (PythonVersion { major, minor }) >= PythonVersion::OLDEST => {
+                Ok(PythonVersion { major, minor })
+            }
+            Some(_) => Err(format!("Python versions before {} are not supported", PythonVersion::OLDEST)),
+            None => Err(format!("expected a version such as {}, got `{}`", PythonVersion::default(), s)),
+        }
+    }
+}
+
+/// Generated Python text, recording where every line came from as it is written.
+#[derive(Default)]
+struct Output {
+    code: String,
+    source_map: SourceMap,
+    /// Prefix for the names of functions lifted out of expressions, chosen so
+    /// that it appears nowhere in the source.
+    lifted_prefix: String,
+    /// Prefix for the temporaries of a `match` lowered to `if`/`elif`, chosen
+    /// the same way.
+    match_prefix: String,
+    /// Numbers lifted functions and `match` temporaries alike.
+    lifted_count: usize,
+    target: PythonVersion,
+}
+
+impl Output {
+    fn new(source: &SourceFile, target: PythonVersion) -> Self {
+        let unique_prefix = |prefix: &str| {
+            let mut prefix = prefix.to_string();
+            while source.text.contains(&prefix) {
+                prefix.insert(0, '_');
+            }
+            prefix
+        };
+        Output {
+            lifted_prefix: unique_prefix("_lambda_"),
+            match_prefix: unique_prefix("_match_"),
+            target,
+            ..Output::default()
+        }
+    }
+
+    /// An empty output that continues this one's numbering of lifted functions.
+    fn nested(&self) -> Self {
+        Output {
+            lifted_prefix: self.lifted_prefix.clone(),
+            match_prefix: self.match_prefix.clone(),
+            lifted_count: self.lifted_count,
+            target: self.target,
+            ..Output::default()
+        }
+    }
+
+    fn append(&mut self, other: Output) {
+        self.code.push_str(&other.code);
+        self.source_map.lines.extend(other.source_map.lines);
+        self.lifted_count = self.lifted_count.max(other.lifted_count);
+    }
+
+    /// Fails if `feature` needs a newer Python than the one targeted.
+    fn require(&self, version: PythonVersion, feature: &str, span: Span) -> Result<(), CodegenError> {
+        if self.target < version {
+            return Err(CodegenError {
+                message: format!("{} requires Python {} or newer, but the target is {}", feature, version, self.target),
+                span,
+                name: None,
+            });
+        }
+        Ok(())
+    }
+
+    fn next_lifted_name(&mut self) -> String {
+        self.lifted_count += 1;
+        format!("{}{}", self.lifted_prefix, self.lifted_count)
+    }
+
+    fn next_match_name(&mut self) -> String {
+        self.lifted_count += 1;
+        format!("{}{}", self.match_prefix, self.lifted_count)
+    }
+
+    fn push_line(&mut self, indent_level: usize, line: &str, span: Span) {
+        self.code.push_str(&"    ".repeat(indent_level)); // 4 spaces/level
+        self.code.push_str(line);
+        self.code.push('\n');
+        // Triple-quoted strings can span several physical lines.
+        for _ in 0..=line.matches('\n').count() {
+            self.source_map.lines.push(span);
+        }
+    }
+}
+
+pub fn generate_python_code(program: &Program, source: &SourceFile, target: PythonVersion) -> Result<GeneratedCode, Diagnostic> {
+    let mut output = Output::new(source, target);
     for statement in &program.statements {
-        generate_statement(statement, 0, &mut output);
+        generate_statement(statement, 0, &mut output)
+            .map_err(|e| e.into_diagnostic(source))?;
     }
-    output
+    Ok(GeneratedCode { python: output.code, source_map: output.source_map })
 }
 
-fn generate_statement(statement: &Statement, indent_level: usize, output: &mut String) {
-    let indent_str = "    ".repeat(indent_level); // 4 spaces/level
+fn generate_statement(statement: &Spanned<Statement>, indent_level: usize, output: &mut Output) -> Result<(), CodegenError> {
+    let span = statement.span;
 
-    match statement {
-        Statement::Assignment { name, value } => {
-            output.push_str(&format!("{}{} = {}\n", indent_str, name, generate_expression(value)));
+    match &statement.node {
+        Statement::Assignment { targets, value } => {
+            let mut line = String::new();
+            for target in targets {
+                line += &format!("{} = ", generate_target(target, false, indent_level, span, output)?);
+            }
+            line += &generate_value(value, indent_level, span, output)?;
+            output.push_line(indent_level, &line, span);
         }
-        Statement::Print { content } => {
-            output.push_str(&format!("{}print({})\n", indent_str, generate_expression(content)));
+        Statement::AugmentedAssignment { target, operator, value } => {
+            let target_str = generate_target(target, false, indent_level, span, output)?;
+            let value_str = generate_value(value, indent_level, span, output)?;
+            output.push_line(indent_level, &format!("{} {}= {}", target_str, operator_str(operator), value_str), span);
+        }
+        Statement::AnnotatedAssignment { target, annotation, value } => {
+            let mut line = format!(
+                "{}: {}",
+                generate_target(target, false, indent_level, span, output)?,
+                generate_expression(annotation, indent_level, span, output)?,
+            );
+            if let Some(value) = value {
+                line += &format!(" = {}", generate_value(value, indent_level, span, output)?);
+            }
+            output.push_line(indent_level, &line, span);
         }
-        Statement::Return { value } => {
-            output.push_str(&format!("{}return {}\n", indent_str, generate_expression(value)));
+        Statement::Return { value: None } => output.push_line(indent_level, "return", span),
+        Statement::Return { value: Some(value) } => {
+            // `return yield x` is not valid Python, so only a tuple is written bare.
+            let value_str = match value {
+                Expression::Tuple(_) => generate_value(value, indent_level, span, output)?,
+                _ => generate_expression(value, indent_level, span, output)?,
+            };
+            output.push_line(indent_level, &format!("return {}", value_str), span);
         }
-        Statement::If { condition, consequence, alternative } => {
-            output.push_str(&format!("{}if {}:\n", indent_str, generate_expression(condition)));
-            generate_block(consequence, indent_level + 1, output);
+        Statement::If { condition, consequence, elif_clauses, alternative } => {
+            // Every condition is generated up front, so that any functions lifted
+            // out of them are defined before the `if` rather than between branches.
+            let condition_str = generate_expression(condition, indent_level, span, output)?;
+            let elif_conditions = elif_clauses.iter()
+                .map(|clause| generate_expression(&clause.condition, indent_level, clause.span, output))
+                .collect::<Result<Vec<_>, _>>()?;
+            output.push_line(indent_level, &format!("if {}:", condition_str), span);
+            generate_block(consequence, indent_level + 1, span, output)?;
+            for (clause, condition) in elif_clauses.iter().zip(elif_conditions) {
+                output.push_line(indent_level, &format!("elif {}:", condition), clause.span);
+                generate_block(&clause.consequence, indent_level + 1, clause.span, output)?;
+            }
             if let Some(alt_block) = alternative {
-                output.push_str(&format!("{}else:\n", indent_str));
-                generate_block(alt_block, indent_level + 1, output);
+                output.push_line(indent_level, "else:", span);
+                generate_block(alt_block, indent_level + 1, span, output)?;
             }
         }
-        Statement::For { iterator, body } => {
-            output.push_str(&format!("{}for {}:\n", indent_str, generate_expression(iterator)));
-            generate_block(body, indent_level + 1, output);
+        Statement::For { is_async, target, iterable, body, alternative } => {
+            let target_str = generate_target(target, false, indent_level, span, output)?;
+            let iterable_str = generate_expression(iterable, indent_level, span, output)?;
+            output.push_line(indent_level, &format!("{}for {} in {}:", async_prefix(*is_async), target_str, iterable_str), span);
+            generate_block(body, indent_level + 1, span, output)?;
+            if let Some(alt_block) = alternative {
+                output.push_line(indent_level, "else:", span);
+                generate_block(alt_block, indent_level + 1, span, output)?;
+            }
         }
         Statement::While { condition, body } => {
-            output.push_str(&format!("{}while {}:\n", indent_str, generate_expression(condition)));
-            generate_block(body, indent_level + 1, output);
-        }
-        Statement::FunctionDef { name, args, body } => {
-            let args_str = args.join(", ");
-            output.push_str(&format!("{}def {}({}):\n", indent_str, name, args_str));
-            generate_block(body, indent_level + 1, output);
-        }
-        Statement::FunctionCall { name, arguments } => {
-            let args_str = arguments.iter()
-                .map(generate_expression)
-                .collect::<Vec<_>>()
+            let condition_str = generate_expression(condition, indent_level, span, output)?;
+            output.push_line(indent_level, &format!("while {}:", condition_str), span);
+            generate_block(body, indent_level + 1, span, output)?;
+        }
+        Statement::With { is_async, items, body } => {
+            let mut items_str = Vec::new();
+            for item in items {
+                let mut item_str = generate_expression(&item.context, indent_level, span, output)?;
+                if let Some(target) = &item.target {
+                    // A bare tuple target would read as more `with` items.
+                    item_str += &format!(" as {}", generate_target(target, true, indent_level, span, output)?);
+                }
+                items_str.push(item_str);
+            }
+            output.push_line(indent_level, &format!("{}with {}:", async_prefix(*is_async), items_str.join(", ")), span);
+            generate_block(body, indent_level + 1, span, output)?;
+        }
+        Statement::Match { subject, cases } if output.target < PythonVersion::MATCH => {
+            generate_match_chain(subject, cases, indent_level, span, output)?;
+        }
+        Statement::Match { subject, cases } => {
+            let subject_str = generate_expression(subject, indent_level, span, output)?;
+            let mut headers = Vec::new();
+            for case in cases {
+                let mut line = format!("case {}", generate_pattern(&case.pattern, indent_level, case.span, output)?);
+                if let Some(guard) = &case.guard {
+                    line += &format!(" if {}", generate_expression(guard, indent_level, case.span, output)?);
+                }
+                line.push(':');
+                headers.push(line);
+            }
+
+            output.push_line(indent_level, &format!("match {}:", subject_str), span);
+            for (case, header) in cases.iter().zip(headers) {
+                output.push_line(indent_level + 1, &header, case.span);
+                generate_block(&case.body, indent_level + 2, case.span, output)?;
+            }
+        }
+        Statement::Try { body, handlers, is_group, alternative, finally } => {
+            if *is_group {
+                output.require(PythonVersion::EXCEPT_GROUPS, "`except*`", handlers.first().map_or(span, |handler| handler.span))?;
+            }
+            let keyword = if *is_group { "except*" } else { "except" };
+            let mut headers = Vec::new();
+            for handler in handlers {
+                let mut line = keyword.to_string();
+                match handler.types.as_slice() {
+                    [] => {}
+                    [single] => line += &format!(" {}", generate_expression(single, indent_level, handler.span, output)?),
+                    types => {
+                        let types = types.iter()
+                            .map(|t| generate_expression(t, indent_level, handler.span, output))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        line += &format!(" ({})", types.join(", "));
+                    }
+                }
+                if let Some(name) = &handler.name {
+                    check_binding(name, handler.span)?;
+                    line += &format!(" as {}", name);
+                }
+                line.push(':');
+                headers.push(line);
+            }
+
+            output.push_line(indent_level, "try:", span);
+            generate_block(body, indent_level + 1, span, output)?;
+            for (handler, header) in handlers.iter().zip(headers) {
+                output.push_line(indent_level, &header, handler.span);
+                generate_block(&handler.body, indent_level + 1, handler.span, output)?;
+            }
+            if let Some(alt_block) = alternative {
+                output.push_line(indent_level, "else:", span);
+                generate_block(alt_block, indent_level + 1, span, output)?;
+            }
+            if let Some(finally_block) = finally {
+                output.push_line(indent_level, "finally:", span);
+                generate_block(finally_block, indent_level + 1, span, output)?;
+            }
+        }
+        Statement::Import { names } => {
+            output.push_line(indent_level, &format!("import {}", generate_import_names(names, span)?), span);
+        }
+        Statement::FromImport { module, level, names } => {
+            let mut module_path = ".".repeat(*level);
+            if let Some(module) = module {
+                for part in module.split('.') {
+                    check_identifier(part, span)?;
+                }
+                module_path += module;
+            }
+            let names = match names {
+                Some(names) => generate_import_names(names, span)?,
+                None => "*".to_string(),
+            };
+            output.push_line(indent_level, &format!("from {} import {}", module_path, names), span);
+        }
+        Statement::Raise { exception, cause } => {
+            let mut line = "raise".to_string();
+            if let Some(exception) = exception {
+                line += &format!(" {}", generate_expression(exception, indent_level, span, output)?);
+            }
+            if let Some(cause) = cause {
+                line += &format!(" from {}", generate_expression(cause, indent_level, span, output)?);
+            }
+            output.push_line(indent_level, &line, span);
+        }
+        Statement::FunctionDef { decorators, is_async, name, parameters, returns, body } => {
+            check_binding(name, span)?;
+            let decorator_lines = generate_decorators(decorators, indent_level, output)?;
+            let mut header = format!("{}def {}({})", async_prefix(*is_async), name, generate_parameters(parameters, indent_level, span, output)?);
+            if let Some(returns) = returns {
+                header += &format!(" -> {}", generate_expression(returns, indent_level, span, output)?);
+            }
+            header.push(':');
+            for line in decorator_lines {
+                output.push_line(indent_level, &line, span);
+            }
+            output.push_line(indent_level, &header, span);
+            generate_block(body, indent_level + 1, span, output)?;
+        }
+        Statement::ClassDef { decorators, name, arguments, body } => {
+            check_binding(name, span)?;
+            let decorator_lines = generate_decorators(decorators, indent_level, output)?;
+            let header = match arguments.as_slice() {
+                [] => format!("class {}:", name),
+                _ => format!("class {}({}):", name, generate_arguments(arguments, indent_level, span, output)?),
+            };
+            for line in decorator_lines {
+                output.push_line(indent_level, &line, span);
+            }
+            output.push_line(indent_level, &header, span);
+            generate_block(body, indent_level + 1, span, output)?;
+        }
+        Statement::Break => output.push_line(indent_level, "break", span),
+        Statement::Continue => output.push_line(indent_level, "continue", span),
+        Statement::Pass => output.push_line(indent_level, "pass", span),
+        Statement::Delete { targets } => {
+            // `del (a,), b` needs the parentheses that `del a,` can do without.
+            let nested = targets.len() > 1;
+            let targets = targets.iter()
+                .map(|target| generate_target(target, nested, indent_level, span, output))
+                .collect::<Result<Vec<_>, _>>()?;
+            output.push_line(indent_level, &format!("del {}", targets.join(", ")), span);
+        }
+        Statement::Assert { test, message } => {
+            let mut line = format!("assert {}", generate_expression(test, indent_level, span, output)?);
+            if let Some(message) = message {
+                line += &format!(", {}", generate_expression(message, indent_level, span, output)?);
+            }
+            output.push_line(indent_level, &line, span);
+        }
+        Statement::Global { names } | Statement::Nonlocal { names } => {
+            for name in names {
+                check_binding(name, span)?;
+            }
+            let keyword = if matches!(statement.node, Statement::Global { .. }) { "global" } else { "nonlocal" };
+            output.push_line(indent_level, &format!("{} {}", keyword, names.join(", ")), span);
+        }
+        Statement::Expression(expression) => {
+            let expression_str = generate_value(expression, indent_level, span, output)?;
+            output.push_line(indent_level, &expression_str, span);
+        }
+    }
+    Ok(())
+}
+
+/// Nested tuples need parentheses; the outermost one reads better without.
+fn generate_target(target: &Target, nested: bool, indent_level: usize, span: Span, output: &mut Output) -> Result<String, CodegenError> {
+    Ok(match target {
+        Target::Identifier(name) => {
+            check_binding(name, span)?;
+            name.clone()
+        }
+        Target::Attribute { object, member } => generate_member_access(object, member, indent_level, span, output)?,
+        Target::Subscript { value, index } => generate_subscript(value, index, indent_level, span, output)?,
+        Target::Starred(inner) => format!("*{}", generate_target(inner, true, indent_level, span, output)?),
+        Target::Tuple(items) => {
+            let mut items_str = items.iter()
+                .map(|item| generate_target(item, true, indent_level, span, output))
+                .collect::<Result<Vec<_>, _>>()?
                 .join(", ");
-            output.push_str(&format!("{}{}({})\n", indent_str, name, args_str));
+            if items.len() == 1 {
+                items_str.push(',');
+            }
+            if nested || items.is_empty() {
+                format!("({})", items_str)
+            } else {
+                items_str
+            }
+        }
+    })
+}
+
+fn generate_expression(expression: &Expression, indent_level: usize, span: Span, output: &mut Output) -> Result<String, CodegenError> {
+    Ok(match expression {
+        Expression::Identifier(name) => {
+            if !PYTHON_CONSTANTS.contains(&name.as_str()) {
+                check_identifier(name, span)?;
+            }
+            name.clone()
+        }
+        Expression::Integer(text) | Expression::Float(text) | Expression::Imaginary(text) => text.clone(),
+        Expression::String(literal) => literal.clone(),
+        Expression::FormattedString { prefix, quote, parts } => {
+            format!("{}{}{}{}", prefix, quote, generate_fstring_parts(parts, quote, prefix.contains(['r', 'R']), indent_level, output)?, quote)
+        }
+        Expression::BinaryOp { left, operator, right } => {
+            let precedence = binary_precedence(*operator);
+            let (left_str, right_str) = if precedence == Precedence::Power {
+                // `**` is right-associative and binds tighter than a unary
+                // operator on its left, but not on its right: `-x ** -y`.
+                (generate_operand(left, precedence, true, indent_level, span, output)?, generate_operand(right, Precedence::Unary, false, indent_level, span, output)?)
+            } else {
+                (generate_operand(left, precedence, false, indent_level, span, output)?, generate_operand(right, precedence, true, indent_level, span, output)?)
+            };
+            format!("{} {} {}", left_str, operator_str(operator), right_str)
+        },
+        Expression::Compare { left, comparisons } => {
+            // A comparison operand written bare would join this chain instead.
+            let mut code = generate_operand(left, Precedence::Comparison, true, indent_level, span, output)?;
+            for (operator, right) in comparisons {
+                let right_str = generate_operand(right, Precedence::Comparison, true, indent_level, span, output)?;
+                code += &format!(" {} {}", operator_str(operator), right_str);
+            }
+            code
+        }
+        Expression::Conditional { test, body, orelse } => {
+            let body_str = generate_operand(body, Precedence::Or, false, indent_level, span, output)?;
+            let test_str = generate_operand(test, Precedence::Or, false, indent_level, span, output)?;
+            // Conditionals nest to the right without parentheses, and a lambda
+            // may follow the `else`.
+            let orelse_str = generate_expression(orelse, indent_level, span, output)?;
+            format!("{} if {} else {}", body_str, test_str, orelse_str)
+        }
+        Expression::UnaryOp { operator, operand } => match operator {
+            UnaryOperator::Not => format!("not {}", generate_operand(operand, Precedence::Not, false, indent_level, span, output)?),
+            UnaryOperator::Neg => format!("-{}", generate_operand(operand, Precedence::Unary, false, indent_level, span, output)?),
+            UnaryOperator::Pos => format!("+{}", generate_operand(operand, Precedence::Unary, false, indent_level, span, output)?),
+            UnaryOperator::Invert => format!("~{}", generate_operand(operand, Precedence::Unary, false, indent_level, span, output)?),
         },
-        Statement::ClassDef { name, body } => {
-            output.push_str(&format!("{}class {}:\n", indent_str, name));
-            generate_block(body, indent_level + 1, output);
+        Expression::MemberAccess { object, member } => generate_member_access(object, member, indent_level, span, output)?,
+        Expression::Call { function, arguments } => {
+            format!("{}({})", generate_operand(function, Precedence::Atom, false, indent_level, span, output)?, generate_arguments(arguments, indent_level, span, output)?)
+        }
+        Expression::List(items) => format!("[{}]", generate_expression_list(items, indent_level, span, output)?),
+        Expression::Tuple(items) => match items.as_slice() {
+            [item] => format!("({},)", generate_expression(item, indent_level, span, output)?),
+            _ => format!("({})", generate_expression_list(items, indent_level, span, output)?),
+        },
+        Expression::Set(items) => format!("{{{}}}", generate_expression_list(items, indent_level, span, output)?),
+        Expression::Dict(entries) => {
+            let entries_str = entries.iter()
+                .map(|(key, value)| Ok(format!("{}: {}", generate_expression(key, indent_level, span, output)?, generate_expression(value, indent_level, span, output)?)))
+                .collect::<Result<Vec<_>, CodegenError>>()?
+                .join(", ");
+            format!("{{{}}}", entries_str)
+        }
+        Expression::Subscript { value, index } => generate_subscript(value, index, indent_level, span, output)?,
+        Expression::ListComprehension { element, generators } => {
+            generate_comprehension(ComprehensionKind::List, element, None, generators, indent_level, span, output)?
+        }
+        Expression::SetComprehension { element, generators } => {
+            generate_comprehension(ComprehensionKind::Set, element, None, generators, indent_level, span, output)?
+        }
+        Expression::DictComprehension { key, value, generators } => {
+            generate_comprehension(ComprehensionKind::Dict, key, Some(value), generators, indent_level, span, output)?
+        }
+        Expression::Generator { element, generators } => {
+            generate_comprehension(ComprehensionKind::Generator, element, None, generators, indent_level, span, output)?
+        }
+        Expression::Yield { .. } | Expression::YieldFrom { .. } => {
+            format!("({})", generate_value(expression, indent_level, span, output)?)
+        }
+        Expression::Await { value } => {
+            format!("await {}", generate_operand(value, Precedence::Atom, false, indent_level, span, output)?)
+        }
+        // Python only accepts a bare `:=` in some places, so it is always
+        // parenthesised.
+        Expression::Walrus { name, value } => {
+            check_binding(name, span)?;
+            format!("({} := {})", name, generate_expression(value, indent_level, span, output)?)
+        }
+        Expression::Lambda { parameters, body } => generate_lambda(parameters, body, indent_level, span, output)?,
+        Expression::Slice { lower, upper, step } => {
+            let mut bound = |bound: &Option<Box<Expression>>| match bound {
+                Some(expression) => generate_expression(expression, indent_level, span, output),
+                None => Ok(String::new()),
+            };
+            match step {
+                Some(_) => format!("{}:{}:{}", bound(lower)?, bound(upper)?, bound(step)?),
+                None => format!("{}:{}", bound(lower)?, bound(upper)?),
+            }
+        }
+    })
+}
+
+fn generate_fstring_parts(parts: &[FStringPart], quote: &str, raw: bool, indent_level: usize, output: &mut Output) -> Result<String, CodegenError> {
+    let mut code = String::new();
+    for part in parts {
+        match part {
+            FStringPart::Literal(text) => code.push_str(text),
+            FStringPart::Replacement { expression, debug, conversion, format_spec, span } => {
+                // A lambda's `:` would start the format spec, so it needs parentheses.
+                let expression_str = generate_operand(expression, Precedence::Conditional, false, indent_level, *span, output)?;
+                if expression_str.contains(quote) || expression_str.contains(['\\', '#']) {
+                    output.require(
+                        PythonVersion::FSTRING_EXPRESSIONS,
+                        "an f-string replacement field with the string's own quotes, a backslash or `#`",
+                        *span,
+                    )?;
+                }
+                // Python prints the text of a debug field as written, so keep it
+                // while it still reads as the same expression in Python.
+                let verbatim = debug.as_deref().filter(|text| {
+                    let written = text.trim_end().strip_suffix('=').unwrap_or(text);
+                    !written.starts_with('{') && same_tokens(written, &expression_str)
+                });
+                if let Some(text) = verbatim {
+                    code.push('{');
+                    code.push_str(text);
+                } else {
+                    if let Some(text) = debug {
+                        code.push_str(&fstring_literal(text, quote, raw));
+                    }
+                    // `{{` would read as an escaped brace, so set and dict literals need a space.
+                    if expression_str.starts_with('{') {
+                        code.push_str("{ ");
+                    } else {
+                        code.push('{');
+                    }
+                    code.push_str(&expression_str);
+                    // A debug field shows the repr unless told otherwise.
+                    if debug.is_some() && conversion.is_none() && format_spec.is_empty() {
+                        code.push_str("!r");
+                    }
+                }
+                if let Some(conversion) = conversion {
+                    code.push('!');
+                    code.push(*conversion);
+                }
+                if !format_spec.is_empty() {
+                    code.push(':');
+                    code.push_str(&generate_fstring_parts(format_spec, quote, raw, indent_level, output)?);
+                }
+                code.push('}');
+            }
+        }
+    }
+    Ok(code)
+}
+
+/// Whether two pieces of source text are the same tokens, ignoring spacing.
+fn same_tokens(a: &str, b: &str) -> bool {
+    tokens(a).map(|range| &a[range]).eq(tokens(b).map(|range| &b[range]))
+}
+
+/// Escapes `text` to appear literally in an f-string with the given quote.
+fn fstring_literal(text: &str, quote: &str, raw: bool) -> String {
+    let mut literal = String::new();
+    for c in text.chars() {
+        match c {
+            '{' => literal.push_str("{{"),
+            '}' => literal.push_str("}}"),
+            '\\' if !raw => literal.push_str("\\\\"),
+            c if quote.starts_with(c) => {
+                if raw {
+                    // A raw string cannot escape its quote, so write it as a field.
+                    literal.push_str(if c == '"' { "{'\"'}" } else { "{\"'\"}" });
+                } else {
+                    literal.push('\\');
+                    literal.push(c);
+                }
+            }
+            '\n' if !raw => literal.push_str("\\n"),
+            c => literal.push(c),
         }
     }
+    literal
 }
 
-fn generate_expression(expression: &Expression) -> String {
+fn generate_member_access(object: &Expression, member: &str, indent_level: usize, span: Span, output: &mut Output) -> Result<String, CodegenError> {
+    check_identifier(member, span)?;
+    let object_str = generate_operand(object, Precedence::Atom, false, indent_level, span, output)?;
+    Ok(match object {
+        // `1.real` would read as the float `1.` followed by `real`.
+        Expression::Integer(text) if is_decimal_integer(text) => format!("({}).{}", object_str, member),
+        _ => format!("{}.{}", object_str, member),
+    })
+}
+
+fn generate_subscript(value: &Expression, index: &Expression, indent_level: usize, span: Span, output: &mut Output) -> Result<String, CodegenError> {
+    let value_str = generate_operand(value, Precedence::Atom, false, indent_level, span, output)?;
+    // Tuple indexes are written bare so that slices inside them stay valid.
+    let index_str = match index {
+        Expression::Tuple(items) if items.len() == 1 => format!("{},", generate_expression(&items[0], indent_level, span, output)?),
+        Expression::Tuple(items) if !items.is_empty() => generate_expression_list(items, indent_level, span, output)?,
+        _ => generate_expression(index, indent_level, span, output)?,
+    };
+    Ok(format!("{}[{}]", value_str, index_str))
+}
+
+/// `a` or `a.b.c`.
+fn is_dotted_name(expression: &Expression) -> bool {
     match expression {
-        Expression::Identifier(name) => name.clone(),
-        Expression::Number(num) => num.to_string(),
-        Expression::String(s) => format!("\"{}\"", s),
-        Expression::BinaryOp { left, operator, right } => {
-            let left_str = generate_expression(left);
-            let right_str = generate_expression(right);
-            match operator.as_str() {
-                "+" => format!("{} + {}", left_str, right_str),
-                _ => format!("{} {} {}", left_str, operator, right_str)
+        Expression::Identifier(_) => true,
+        Expression::MemberAccess { object, .. } => is_dotted_name(object),
+        _ => false,
+    }
+}
+
+/// `1_000` or `0`, but not `0x10`, `0o7` or `0b1`.
+fn is_decimal_integer(text: &str) -> bool {
+    !matches!(text.get(..2), Some("0x" | "0X" | "0o" | "0O" | "0b" | "0B"))
+}
+
+/// Generates the value of an expression statement or assignment, the only
+/// places where a `yield` or a tuple needs no parentheses.
+fn generate_value(expression: &Expression, indent_level: usize, span: Span, output: &mut Output) -> Result<String, CodegenError> {
+    Ok(match expression {
+        Expression::Yield { value: None } => "yield".to_string(),
+        Expression::Yield { value: Some(value) } => match value.as_ref() {
+            Expression::Tuple(items) if items.len() == 1 => {
+                format!("yield {},", generate_expression(&items[0], indent_level, span, output)?)
             }
+            Expression::Tuple(items) if !items.is_empty() => {
+                format!("yield {}", generate_expression_list(items, indent_level, span, output)?)
+            }
+            _ => format!("yield {}", generate_expression(value, indent_level, span, output)?),
         },
-        Expression::MemberAccess { object, member } => {
-            format!("{}.{}", generate_expression(object), member)
+        Expression::YieldFrom { value } => {
+            format!("yield from {}", generate_expression(value, indent_level, span, output)?)
         }
-        Expression::FunctionCall { name, args } => {
-            let args_str = args.iter()
-                .map(generate_expression)
-                .collect::<Vec<_>>()
-                .join(", ");
-            format!("{}({})", name, args_str)
+        Expression::Tuple(items) if items.len() == 1 => {
+            format!("{},", generate_expression(&items[0], indent_level, span, output)?)
+        }
+        Expression::Tuple(items) if !items.is_empty() => generate_expression_list(items, indent_level, span, output)?,
+        _ => generate_expression(expression, indent_level, span, output)?,
+    })
+}
+
+fn generate_pattern(pattern: &Pattern, indent_level: usize, span: Span, output: &mut Output) -> Result<String, CodegenError> {
+    let mut list = |patterns: &[Pattern]| -> Result<String, CodegenError> {
+        Ok(patterns.iter()
+            .map(|pattern| generate_pattern(pattern, indent_level, span, output))
+            .collect::<Result<Vec<_>, _>>()?
+            .join(", "))
+    };
+    Ok(match pattern {
+        Pattern::Literal(value) | Pattern::Value(value) => generate_expression(value, indent_level, span, output)?,
+        Pattern::Capture(name) => {
+            check_binding(name, span)?;
+            name.clone()
+        }
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Sequence(items) => format!("[{}]", list(items)?),
+        Pattern::Star(Some(name)) => {
+            check_binding(name, span)?;
+            format!("*{}", name)
+        }
+        Pattern::Star(None) => "*_".to_string(),
+        Pattern::Mapping { entries, rest } => {
+            let mut items = Vec::new();
+            for (key, value) in entries {
+                items.push(format!(
+                    "{}: {}",
+                    generate_expression(key, indent_level, span, output)?,
+                    generate_pattern(value, indent_level, span, output)?,
+                ));
+            }
+            if let Some(rest) = rest {
+                check_binding(rest, span)?;
+                items.push(format!("**{}", rest));
+            }
+            format!("{{{}}}", items.join(", "))
+        }
+        Pattern::Class { class, positional, keywords } => {
+            let mut arguments = vec![list(positional)?];
+            for (name, pattern) in keywords {
+                check_identifier(name, span)?;
+                arguments.push(format!("{}={}", name, generate_pattern(pattern, indent_level, span, output)?));
+            }
+            arguments.retain(|argument| !argument.is_empty());
+            format!("{}({})", generate_expression(class, indent_level, span, output)?, arguments.join(", "))
+        }
+        Pattern::Or(alternatives) => {
+            let mut code = Vec::new();
+            for alternative in alternatives {
+                let alternative_str = generate_pattern(alternative, indent_level, span, output)?;
+                // Only closed patterns may appear between `|`s.
+                if matches!(alternative, Pattern::Or(_) | Pattern::As { .. }) {
+                    code.push(format!("({})", alternative_str));
+                } else {
+                    code.push(alternative_str);
+                }
+            }
+            code.join(" | ")
+        }
+        Pattern::As { pattern, name } => {
+            check_binding(name, span)?;
+            format!("{} as {}", generate_pattern(pattern, indent_level, span, output)?, name)
+        }
+    })
+}
+
+/// Lowers a `match` to an `if`/`elif` chain for Python versions without one.
+/// The subject is evaluated once into a temporary, and each pattern becomes a
+/// condition that binds its captures with `:=` as it is tested.
+fn generate_match_chain(subject: &Expression, cases: &[MatchCase], indent_level: usize, span: Span, output: &mut Output) -> Result<(), CodegenError> {
+    let subject_str = generate_expression(subject, indent_level, span, output)?;
+    let subject_name = output.next_match_name();
+    let mut abc = None;
+    let mut conditions = Vec::new();
+    for case in cases {
+        let mut condition = pattern_conditions(&case.pattern, &subject_name, &mut abc, indent_level, case.span, output)?;
+        if let Some(guard) = &case.guard {
+            condition.push(generate_operand(guard, Precedence::And, false, indent_level, case.span, output)?);
         }
+        conditions.push(condition);
+    }
 
-        _ => {println!("Unknown expression: {:?}", expression); "UNKNOWN".to_string()}
+    if let Some(abc) = &abc {
+        output.push_line(indent_level, &format!("import collections.abc as {}", abc), span);
     }
+    output.push_line(indent_level, &format!("{} = {}", subject_name, subject_str), span);
+    for (i, (case, condition)) in cases.iter().zip(conditions).enumerate() {
+        let header = match (i, condition.is_empty()) {
+            (0, true) => "if True:".to_string(),
+            (_, true) => "else:".to_string(),
+            (0, false) => format!("if {}:", condition.join(" and ")),
+            (_, false) => format!("elif {}:", condition.join(" and ")),
+        };
+        output.push_line(indent_level, &header, case.span);
+        generate_block(&case.body, indent_level + 1, case.span, output)?;
+    }
+    Ok(())
 }
 
-fn generate_block(block: &Block, indent_level: usize, output: &mut String) {
+/// The tests that `subject` must pass to match `pattern`, to be joined with
+/// `and`. Captures are bound by tests that always succeed.
+fn pattern_conditions(
+    pattern: &Pattern,
+    subject: &str,
+    abc: &mut Option<String>,
+    indent_level: usize,
+    span: Span,
+    output: &mut Output,
+) -> Result<Vec<String>, CodegenError> {
+    let bind = |name: &str, value: &str| -> Result<String, CodegenError> {
+        check_binding(name, span)?;
+        Ok(format!("(({} := {}) or True)", name, value))
+    };
+    let mut conditions = Vec::new();
+    match pattern {
+        Pattern::Literal(value @ Expression::Identifier(_)) => {
+            conditions.push(format!("{} is {}", subject, generate_expression(value, indent_level, span, output)?));
+        }
+        Pattern::Literal(value) | Pattern::Value(value) => {
+            conditions.push(format!("{} == {}", subject, generate_expression(value, indent_level, span, output)?));
+        }
+        Pattern::Capture(name) => conditions.push(bind(name, subject)?),
+        Pattern::Wildcard | Pattern::Star(_) => {}
+        Pattern::Sequence(items) => {
+            let abc_name = abc.get_or_insert_with(|| output.next_match_name()).clone();
+            conditions.push(format!("isinstance({}, {}.Sequence)", subject, abc_name));
+            conditions.push(format!("not isinstance({}, (str, bytes, bytearray))", subject));
+            let star = items.iter().position(|item| matches!(item, Pattern::Star(_)));
+            match star {
+                Some(_) => conditions.push(format!("len({}) >= {}", subject, items.len() - 1)),
+                None => conditions.push(format!("len({}) == {}", subject, items.len())),
+            }
+            for (i, item) in items.iter().enumerate() {
+                let element = match star {
+                    Some(star) if i > star => format!("{}[{}]", subject, i as isize - items.len() as isize),
+                    _ => format!("{}[{}]", subject, i),
+                };
+                match item {
+                    Pattern::Star(Some(name)) => {
+                        let after = items.len() - i - 1;
+                        let slice = match after {
+                            0 => format!("list({}[{}:])", subject, i),
+                            _ => format!("list({}[{}:-{}])", subject, i, after),
+                        };
+                        conditions.push(bind(name, &slice)?);
+                    }
+                    _ => conditions.extend(pattern_conditions(item, &element, abc, indent_level, span, output)?),
+                }
+            }
+        }
+        Pattern::Mapping { entries, rest } => {
+            let abc_name = abc.get_or_insert_with(|| output.next_match_name()).clone();
+            conditions.push(format!("isinstance({}, {}.Mapping)", subject, abc_name));
+            let mut keys = Vec::new();
+            for (key, value) in entries {
+                let key = generate_expression(key, indent_level, span, output)?;
+                conditions.push(format!("{} in {}", key, subject));
+                let element = format!("{}[{}]", subject, key);
+                conditions.extend(pattern_conditions(value, &element, abc, indent_level, span, output)?);
+                keys.push(key);
+            }
+            if let Some(rest) = rest {
+                conditions.push(bind(rest, &format!("dict({})", subject))?);
+                for key in keys {
+                    conditions.push(format!("({}.pop({}) or True)", rest, key));
+                }
+            }
+        }
+        Pattern::Class { class, positional, keywords } => {
+            let class = generate_expression(class, indent_level, span, output)?;
+            conditions.push(format!("isinstance({}, {})", subject, class));
+            if let [single] = positional.as_slice()
+                && SELF_MATCHING_CLASSES.contains(&class.as_str())
+            {
+                conditions.extend(pattern_conditions(single, subject, abc, indent_level, span, output)?);
+            } else {
+                for (i, item) in positional.iter().enumerate() {
+                    let attribute = format!("{}.__match_args__[{}]", class, i);
+                    conditions.push(format!("hasattr({}, {})", subject, attribute));
+                    let element = format!("getattr({}, {})", subject, attribute);
+                    conditions.extend(pattern_conditions(item, &element, abc, indent_level, span, output)?);
+                }
+            }
+            for (name, item) in keywords {
+                check_identifier(name, span)?;
+                conditions.push(format!("hasattr({}, \"{}\")", subject, name));
+                let element = format!("{}.{}", subject, name);
+                conditions.extend(pattern_conditions(item, &element, abc, indent_level, span, output)?);
+            }
+        }
+        Pattern::Or(alternatives) => {
+            let mut code = Vec::new();
+            for alternative in alternatives {
+                let alternative_conditions = pattern_conditions(alternative, subject, abc, indent_level, span, output)?;
+                match alternative_conditions.as_slice() {
+                    [] => code.push("True".to_string()),
+                    _ => code.push(alternative_conditions.join(" and ")),
+                }
+            }
+            conditions.push(format!("({})", code.join(" or ")));
+        }
+        Pattern::As { pattern, name } => {
+            conditions.extend(pattern_conditions(pattern, subject, abc, indent_level, span, output)?);
+            conditions.push(bind(name, subject)?);
+        }
+    }
+    Ok(conditions)
+}
+
+#[derive(Clone, Copy)]
+enum ComprehensionKind {
+    List,
+    Set,
+    Dict,
+    Generator,
+}
+
+/// Generates a comprehension. `value` is set for dict comprehensions, where
+/// `element` is the key.
+///
+/// Only the first iterable is evaluated in the enclosing scope; the rest runs
+/// in the comprehension's own scope, where its loop variables are bound. If a
+/// function has to be lifted out of that part, it could not see them, so the
+/// comprehension is written out as a lifted generator function instead.
+fn generate_comprehension(
+    kind: ComprehensionKind,
+    element: &Expression,
+    value: Option<&Expression>,
+    generators: &[Comprehension],
+    indent_level: usize,
+    span: Span,
+    output: &mut Output,
+) -> Result<String, CodegenError> {
+    let first_iterable = generate_operand(&generators[0].iterable, Precedence::Or, false, indent_level, span, output)?;
+
+    let mut scoped = output.nested();
+    let mut clauses = Vec::new();
+    for (i, generator) in generators.iter().enumerate() {
+        let target = generate_target(&generator.target, false, indent_level, span, &mut scoped)?;
+        let iterable = match i {
+            0 => first_iterable.clone(),
+            _ => generate_operand(&generator.iterable, Precedence::Or, false, indent_level, span, &mut scoped)?,
+        };
+        clauses.push(format!("for {} in {}", target, iterable));
+        for condition in &generator.conditions {
+            clauses.push(format!("if {}", generate_operand(condition, Precedence::Or, false, indent_level, span, &mut scoped)?));
+        }
+    }
+    let element_str = match value {
+        Some(value) => format!(
+            "{}: {}",
+            generate_operand(element, Precedence::Conditional, false, indent_level, span, &mut scoped)?,
+            generate_expression(value, indent_level, span, &mut scoped)?,
+        ),
+        None => generate_expression(element, indent_level, span, &mut scoped)?,
+    };
+    if scoped.code.is_empty() {
+        output.append(scoped);
+        let (open, close) = match kind {
+            ComprehensionKind::List => ("[", "]"),
+            ComprehensionKind::Set | ComprehensionKind::Dict => ("{", "}"),
+            ComprehensionKind::Generator => ("(", ")"),
+        };
+        return Ok(format!("{}{} {}{}", open, element_str, clauses.join(" "), close));
+    }
+
+    output.lifted_count = scoped.lifted_count;
+    let name = output.next_lifted_name();
+    let parameter = format!("{}iterable", output.lifted_prefix);
+    output.push_line(indent_level, &format!("def {}({}):", name, parameter), span);
+    let mut level = indent_level + 1;
+    for (i, generator) in generators.iter().enumerate() {
+        let target = generate_target(&generator.target, false, level, span, output)?;
+        let iterable = match i {
+            0 => parameter.clone(),
+            _ => generate_expression(&generator.iterable, level, span, output)?,
+        };
+        output.push_line(level, &format!("for {} in {}:", target, iterable), span);
+        level += 1;
+        for condition in &generator.conditions {
+            let condition = generate_expression(condition, level, span, output)?;
+            output.push_line(level, &format!("if {}:", condition), span);
+            level += 1;
+        }
+    }
+    let yielded = match value {
+        Some(value) => format!(
+            "{}, {}",
+            generate_expression(element, level, span, output)?,
+            generate_expression(value, level, span, output)?,
+        ),
+        None => generate_expression(element, level, span, output)?,
+    };
+    output.push_line(level, &format!("yield {}", yielded), span);
+
+    let call = format!("{}({})", name, first_iterable);
+    Ok(match kind {
+        ComprehensionKind::List => format!("list({})", call),
+        ComprehensionKind::Set => format!("set({})", call),
+        ComprehensionKind::Dict => format!("dict({})", call),
+        ComprehensionKind::Generator => call,
+    })
+}
+
+/// Generates a `lambda`, or lifts the function into a `def` written before the
+/// current statement and returns its name. Block bodies are always lifted, as
+/// are expression bodies that need it: annotated parameters, or something in
+/// the body that was itself lifted and must stay within the parameters' scope.
+fn generate_lambda(parameters: &[Parameter], body: &LambdaBody, indent_level: usize, span: Span, output: &mut Output) -> Result<String, CodegenError> {
+    let parameters_str = generate_parameters(parameters, indent_level, span, output)?;
+    match body {
+        LambdaBody::Expression(body) => {
+            let mut body_output = output.nested();
+            let body_str = generate_expression(body, indent_level + 1, span, &mut body_output)?;
+            let annotated = parameters.iter().any(|p| p.annotation.is_some());
+            if body_output.code.is_empty() && !annotated {
+                output.append(body_output);
+                return Ok(match parameters_str.as_str() {
+                    "" => format!("lambda: {}", body_str),
+                    _ => format!("lambda {}: {}", parameters_str, body_str),
+                });
+            }
+            output.lifted_count = body_output.lifted_count;
+            let name = output.next_lifted_name();
+            output.push_line(indent_level, &format!("def {}({}):", name, parameters_str), span);
+            output.append(body_output);
+            output.push_line(indent_level + 1, &format!("return {}", body_str), span);
+            Ok(name)
+        }
+        LambdaBody::Block(block) => {
+            let name = output.next_lifted_name();
+            output.push_line(indent_level, &format!("def {}({}):", name, parameters_str), span);
+            generate_block(block, indent_level + 1, span, output)?;
+            Ok(name)
+        }
+    }
+}
+
+/// Returns the `@` lines for a definition. They are pushed by the caller once
+/// the whole header is generated, so nothing lifted lands between them.
+fn generate_decorators(decorators: &[Spanned<Expression>], indent_level: usize, output: &mut Output) -> Result<Vec<String>, CodegenError> {
+    decorators.iter()
+        .map(|Spanned { node: decorator, span }| {
+            let callee = match decorator {
+                Expression::Call { function, .. } => function,
+                _ => decorator,
+            };
+            if !is_dotted_name(callee) {
+                output.require(PythonVersion::DECORATOR_EXPRESSIONS, "a decorator that is not a dotted name or a call of one", *span)?;
+            }
+            Ok(format!("@{}", generate_expression(decorator, indent_level, *span, output)?))
+        })
+        .collect()
+}
+
+fn generate_arguments(arguments: &[Argument], indent_level: usize, span: Span, output: &mut Output) -> Result<String, CodegenError> {
+    // A generator expression that is the only argument needs no parentheses
+    // of its own: `sum(x for x in xs)`.
+    if let [Argument::Positional(generator @ Expression::Generator { .. })] = arguments {
+        let code = generate_expression(generator, indent_level, span, output)?;
+        return Ok(match code.strip_prefix('(').and_then(|code| code.strip_suffix(')')) {
+            Some(inner) => inner.to_string(),
+            None => code,
+        });
+    }
+
+    let mut generated = Vec::new();
+    for argument in arguments {
+        generated.push(match argument {
+            Argument::Positional(value) => generate_expression(value, indent_level, span, output)?,
+            Argument::Keyword { name, value } => {
+                check_identifier(name, span)?;
+                format!("{}={}", name, generate_expression(value, indent_level, span, output)?)
+            }
+            Argument::Unpack(value) => format!("*{}", generate_expression(value, indent_level, span, output)?),
+            Argument::UnpackKeywords(value) => format!("**{}", generate_expression(value, indent_level, span, output)?),
+        });
+    }
+    Ok(generated.join(", "))
+}
+
+/// Writes the parameters back out with the `/` and `*` markers their kinds imply.
+fn generate_parameters(parameters: &[Parameter], indent_level: usize, span: Span, output: &mut Output) -> Result<String, CodegenError> {
+    let mut generated = Vec::new();
+    let mut previous = None;
+    for parameter in parameters {
+        check_binding(&parameter.name, span)?;
+        if previous == Some(ParameterKind::PositionalOnly) && parameter.kind != ParameterKind::PositionalOnly {
+            generated.push("/".to_string());
+        }
+        if parameter.kind == ParameterKind::KeywordOnly
+            && !matches!(previous, Some(ParameterKind::KeywordOnly | ParameterKind::VarPositional))
+        {
+            generated.push("*".to_string());
+        }
+
+        let mut text = match parameter.kind {
+            ParameterKind::VarPositional => format!("*{}", parameter.name),
+            ParameterKind::VarKeyword => format!("**{}", parameter.name),
+            _ => parameter.name.clone(),
+        };
+        if let Some(annotation) = &parameter.annotation {
+            text += &format!(": {}", generate_expression(annotation, indent_level, span, output)?);
+        }
+        if let Some(default) = &parameter.default {
+            // PEP 8 spaces the `=` only when there is an annotation.
+            let equals = if parameter.annotation.is_some() { " = " } else { "=" };
+            text += &format!("{}{}", equals, generate_expression(default, indent_level, span, output)?);
+        }
+        generated.push(text);
+        previous = Some(parameter.kind);
+    }
+    if previous == Some(ParameterKind::PositionalOnly) {
+        generated.push("/".to_string());
+    }
+    Ok(generated.join(", "))
+}
+
+fn generate_expression_list(items: &[Expression], indent_level: usize, span: Span, output: &mut Output) -> Result<String, CodegenError> {
+    Ok(items.iter()
+        .map(|arg| generate_expression(arg, indent_level, span, output))
+        .collect::<Result<Vec<_>, _>>()?
+        .join(", "))
+}
+
+/// How tightly Python expressions bind, loosest first. Used to decide where
+/// generated code needs parentheses, since the AST does not keep them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Lambda,
+    Conditional,
+    Or,
+    And,
+    Not,
+    Comparison,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
+    Additive,
+    Multiplicative,
+    Unary,
+    Power,
+    Await,
+    Atom,
+}
+
+fn binary_precedence(operator: Operator) -> Precedence {
+    match operator {
+        Operator::Or => Precedence::Or,
+        Operator::And => Precedence::And,
+        Operator::Eq | Operator::NotEq | Operator::Lt | Operator::Gt | Operator::LtEq
+        | Operator::GtEq | Operator::In | Operator::NotIn | Operator::Is | Operator::IsNot => Precedence::Comparison,
+        Operator::BitOr => Precedence::BitOr,
+        Operator::BitXor => Precedence::BitXor,
+        Operator::BitAnd => Precedence::BitAnd,
+        Operator::LShift | Operator::RShift => Precedence::Shift,
+        Operator::Add | Operator::Sub => Precedence::Additive,
+        Operator::Mul | Operator::Div | Operator::FloorDiv | Operator::Mod | Operator::MatMul => Precedence::Multiplicative,
+        Operator::Pow => Precedence::Power,
+    }
+}
+
+fn expression_precedence(expression: &Expression) -> Precedence {
+    match expression {
+        Expression::BinaryOp { operator, .. } => binary_precedence(*operator),
+        Expression::Compare { .. } => Precedence::Comparison,
+        Expression::Conditional { .. } => Precedence::Conditional,
+        Expression::UnaryOp { operator: UnaryOperator::Not, .. } => Precedence::Not,
+        Expression::UnaryOp { .. } => Precedence::Unary,
+        Expression::Await { .. } => Precedence::Await,
+        // Annotated parameters make a lambda be lifted into a `def` by name.
+        Expression::Lambda { parameters, body: LambdaBody::Expression(_) }
+            if parameters.iter().all(|p| p.annotation.is_none()) => Precedence::Lambda,
+        _ => Precedence::Atom,
+    }
+}
+
+/// Generates a sub-expression, parenthesised if it binds more loosely than
+/// `min` (or no more tightly than `min`, when `strict` is set).
+fn generate_operand(expression: &Expression, min: Precedence, strict: bool, indent_level: usize, span: Span, output: &mut Output) -> Result<String, CodegenError> {
+    let precedence = expression_precedence(expression);
+    let code = generate_expression(expression, indent_level, span, output)?;
+    if precedence < min || (strict && precedence == min) {
+        Ok(format!("({})", code))
+    } else {
+        Ok(code)
+    }
+}
+
+fn async_prefix(is_async: bool) -> &'static str {
+    if is_async { "async " } else { "" }
+}
+
+fn operator_str(operator: &Operator) -> &'static str {
+    match operator {
+        Operator::Add => "+",
+        Operator::Sub => "-",
+        Operator::Mul => "*",
+        Operator::Div => "/",
+        Operator::FloorDiv => "//",
+        Operator::Mod => "%",
+        Operator::Pow => "**",
+        Operator::MatMul => "@",
+        Operator::LShift => "<<",
+        Operator::RShift => ">>",
+        Operator::BitAnd => "&",
+        Operator::BitOr => "|",
+        Operator::BitXor => "^",
+        Operator::Eq => "==",
+        Operator::NotEq => "!=",
+        Operator::Lt => "<",
+        Operator::Gt => ">",
+        Operator::LtEq => "<=",
+        Operator::GtEq => ">=",
+        Operator::In => "in",
+        Operator::NotIn => "not in",
+        Operator::Is => "is",
+        Operator::IsNot => "is not",
+        Operator::And => "and",
+        Operator::Or => "or",
+    }
+}
+
+/// Rejects names that Python would not accept as an identifier.
+fn check_identifier(name: &str, span: Span) -> Result<(), CodegenError> {
+    if PYTHON_KEYWORDS.contains(&name) {
+        return Err(CodegenError {
+            message: format!("`{}` is a reserved word in Python and cannot be used as an identifier", name),
+            span,
+            name: Some(name.to_string()),
+        });
+    }
+    Ok(())
+}
+
+fn generate_import_names(names: &[ImportAlias], span: Span) -> Result<String, CodegenError> {
+    let mut generated = Vec::new();
+    for import in names {
+        for part in import.name.split('.') {
+            check_identifier(part, span)?;
+        }
+        match &import.alias {
+            Some(alias) => {
+                check_binding(alias, span)?;
+                generated.push(format!("{} as {}", import.name, alias));
+            }
+            None => generated.push(import.name.clone()),
+        }
+    }
+    Ok(generated.join(", "))
+}
+
+/// Like `check_identifier`, but also rejects the constants `True`, `False` and `None`.
+fn check_binding(name: &str, span: Span) -> Result<(), CodegenError> {
+    if PYTHON_CONSTANTS.contains(&name) {
+        return Err(CodegenError {
+            message: format!("cannot assign to `{}`", name),
+            span,
+            name: Some(name.to_string()),
+        });
+    }
+    check_identifier(name, span)
+}
+
+fn generate_block(block: &Block, indent_level: usize, span: Span, output: &mut Output) -> Result<(), CodegenError> {
     if block.statements.is_empty() {
-        output.push_str(&format!("{}    pass\n", "    ".repeat(indent_level - 1)));
+        output.push_line(indent_level, "pass", span);
     } else {
         for statement in &block.statements {
-            generate_statement(statement, indent_level, output);
+            generate_statement(statement, indent_level, output)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_bython_code;
+
+    /// The Python generated for a whole program.
+    fn generated(code: &str) -> String {
+        let source = SourceFile::new("test.bython", code);
+        let parsed = parse_bython_code(&source);
+        assert!(parsed.diagnostics.is_empty(), "`{}` did not parse", code);
+        generate_python_code(&parsed.program, &source, PythonVersion::default()).unwrap().python
+    }
+
+    /// The line and caret underline of the error that generating a program for
+    /// Python 3.8 stops at.
+    fn underlined_error(code: &str) -> String {
+        let source = SourceFile::new("test.bython", code);
+        let parsed = parse_bython_code(&source);
+        assert!(parsed.diagnostics.is_empty(), "`{}` did not parse", code);
+        let error = generate_python_code(&parsed.program, &source, PythonVersion::OLDEST).unwrap_err();
+        let rendered = error.render(&source);
+        let lines: Vec<_> = rendered.lines().collect();
+        lines[lines.len() - 2..].join("\n")
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_part() {
+        assert_eq!(underlined_error("y = f(1, pass);"), "1 | y = f(1, pass);\n  |          ^^^^");
+        assert_eq!(underlined_error("fn f(a, class) {}"), "1 | fn f(a, class) {}\n  |         ^^^^^");
+        assert_eq!(underlined_error("x.None = 1;"), "1 | x.None = 1;\n  |   ^^^^");
+        assert_eq!(underlined_error("for x, True in xs {}"), "1 | for x, True in xs {}\n  |        ^^^^");
+        assert_eq!(
+            underlined_error("@cache\n@x[0]\nfn f() {}"),
+            "2 | @x[0]\n  | ^^^^^",
+        );
+        assert_eq!(
+            underlined_error("y = f\"{a} {b[\"k\"]}\";"),
+            "1 | y = f\"{a} {b[\"k\"]}\";\n  |           ^^^^^^^^",
+        );
+        assert_eq!(
+            underlined_error("try { f(); } except* E { g(); }"),
+            "1 | try { f(); } except* E { g(); }\n  |              ^^^^^^^^^^^^^^^^^^",
+        );
+    }
+
+    #[test]
+    fn for_loop_targets() {
+        assert_eq!(generated("for x, in pairs {}"), "for x, in pairs:\n    pass\n");
+        assert_eq!(generated("for (a, b), *c in xs {}"), "for (a, b), *c in xs:\n    pass\n");
+        assert_eq!(generated("for i # a,\n in xs {}"), "for i in xs:\n    pass\n");
+    }
+
+    #[test]
+    fn subscript_tuples() {
+        assert_eq!(generated("y = a[m[1,]];"), "y = a[m[1,]]\n");
+        assert_eq!(generated("y = a[1, 2];"), "y = a[1, 2]\n");
+        assert_eq!(generated("y = a[1:2, ::3];"), "y = a[1:2, ::3]\n");
+    }
+
+    #[test]
+    fn numbers_keep_their_source_text() {
+        let literals = [
+            "0", "0_0", "1_000_000", "123456789012345678901234567890",
+            "0xFF", "0XdE_aD", "0o17", "0O7", "0b1010", "0B1_0",
+            "1.5", ".5", "5.", "1_0.0_1", "1e10", "1E-5", "2.5e+3", "1_0e1_0", "00.5",
+            "3j", "1.5J", ".5j", "1e3j", "1_0j", "0j",
+        ];
+        for literal in literals {
+            assert_eq!(generated(&format!("y = {};", literal)), format!("y = {}\n", literal));
+        }
+        assert_eq!(generated("y = -0x1 + 2.5e-3j;"), "y = -0x1 + 2.5e-3j\n");
+        assert_eq!(
+            generated("match x { case -1.5 + 2j => {} }"),
+            "match x:\n    case -1.5 + 2j:\n        pass\n",
+        );
+    }
+
+    #[test]
+    fn power_and_unary_operators() {
+        assert_eq!(generated("print(-x ** 2);"), "print(-x ** 2)\n");
+        assert_eq!(generated("print((-x) ** 2);"), "print((-x) ** 2)\n");
+        assert_eq!(generated("print(2 ** -x);"), "print(2 ** -x)\n");
+        assert_eq!(generated("print(not a == b);"), "print(not a == b)\n");
+    }
+
+    #[test]
+    fn postfix_chains() {
+        assert_eq!(generated("y = a.b.c(x).d[0](y);"), "y = a.b.c(x).d[0](y)\n");
+        assert_eq!(generated("y = 1 .real;"), "y = (1).real\n");
+        assert_eq!(generated("y = 0x1.real;"), "y = 0x1.real\n");
+    }
+
+    #[test]
+    fn attribute_target_of_operator() {
+        assert_eq!(generated("(a or b).x = 1;"), "(a or b).x = 1\n");
+        assert_eq!(generated("(-a).x = 1;"), "(-a).x = 1\n");
+    }
+
+    /// The condition that a lowered `case` tests, with the match temporaries
+    /// renamed to `s` for the subject and `abc` for `collections.abc`.
+    fn lowered_condition(pattern: &str) -> String {
+        let source = SourceFile::new("test.bython", format!("match v {{ case {} => {{}} }}", pattern));
+        let parsed = parse_bython_code(&source);
+        assert!(parsed.diagnostics.is_empty(), "`{}` did not parse", pattern);
+        let target = PythonVersion { major: 3, minor: 9 };
+        let python = generate_python_code(&parsed.program, &source, target).unwrap().python;
+        let line = python.lines().find(|line| line.starts_with("if ")).unwrap();
+        let mut condition = line["if ".len()..line.len() - 1].to_string();
+        if let Some(abc) = python.lines().find_map(|line| line.strip_prefix("import collections.abc as ")) {
+            condition = condition.replace(abc, "abc");
         }
+        let subject = python.lines().find_map(|line| line.strip_suffix(" = v")).unwrap();
+        condition.replace(subject, "s")
+    }
+
+    #[test]
+    fn sequence_pattern() {
+        assert_eq!(
+            lowered_condition("[1, x]"),
+            "isinstance(s, abc.Sequence) and not isinstance(s, (str, bytes, bytearray)) and len(s) == 2 \
+             and s[0] == 1 and ((x := s[1]) or True)",
+        );
+    }
+
+    #[test]
+    fn sequence_pattern_with_star() {
+        assert_eq!(
+            lowered_condition("[a, *rest, b, c]"),
+            "isinstance(s, abc.Sequence) and not isinstance(s, (str, bytes, bytearray)) and len(s) >= 3 \
+             and ((a := s[0]) or True) and ((rest := list(s[1:-2])) or True) \
+             and ((b := s[-2]) or True) and ((c := s[-1]) or True)",
+        );
+        assert_eq!(
+            lowered_condition("[a, *_]"),
+            "isinstance(s, abc.Sequence) and not isinstance(s, (str, bytes, bytearray)) and len(s) >= 1 \
+             and ((a := s[0]) or True)",
+        );
+        assert_eq!(
+            lowered_condition("[*rest]"),
+            "isinstance(s, abc.Sequence) and not isinstance(s, (str, bytes, bytearray)) and len(s) >= 0 \
+             and ((rest := list(s[0:])) or True)",
+        );
+    }
+
+    #[test]
+    fn nested_sequence_pattern() {
+        assert_eq!(
+            lowered_condition("[[x], _]"),
+            "isinstance(s, abc.Sequence) and not isinstance(s, (str, bytes, bytearray)) and len(s) == 2 \
+             and isinstance(s[0], abc.Sequence) and not isinstance(s[0], (str, bytes, bytearray)) \
+             and len(s[0]) == 1 and ((x := s[0][0]) or True)",
+        );
+    }
+
+    #[test]
+    fn mapping_pattern() {
+        assert_eq!(
+            lowered_condition("{\"k\": 1, \"v\": v}"),
+            "isinstance(s, abc.Mapping) and \"k\" in s and s[\"k\"] == 1 and \"v\" in s and ((v := s[\"v\"]) or True)",
+        );
+    }
+
+    #[test]
+    fn mapping_pattern_with_rest() {
+        assert_eq!(
+            lowered_condition("{\"k\": k, **rest}"),
+            "isinstance(s, abc.Mapping) and \"k\" in s and ((k := s[\"k\"]) or True) \
+             and ((rest := dict(s)) or True) and (rest.pop(\"k\") or True)",
+        );
+    }
+
+    #[test]
+    fn class_pattern() {
+        assert_eq!(
+            lowered_condition("Point(0, y=y)"),
+            "isinstance(s, Point) and hasattr(s, Point.__match_args__[0]) \
+             and getattr(s, Point.__match_args__[0]) == 0 and hasattr(s, \"y\") and ((y := s.y) or True)",
+        );
+    }
+
+    #[test]
+    fn self_matching_class_pattern() {
+        assert_eq!(lowered_condition("int(n)"), "isinstance(s, int) and ((n := s) or True)");
+        assert_eq!(
+            lowered_condition("str() | bytes()"),
+            "(isinstance(s, str) or isinstance(s, bytes))",
+        );
+    }
+
+    #[test]
+    fn augmented_attribute_target() {
+        assert_eq!(generated("(a or b).y += 2;"), "(a or b).y += 2\n");
+    }
+
+    #[test]
+    fn comment_ending_in_comma() {
+        assert_eq!(generated("x = 1 # note,\n;"), "x = 1\n");
+        assert_eq!(generated("x = 1,;"), "x = 1,\n");
+    }
+
+    #[test]
+    fn chained_comparisons() {
+        assert_eq!(generated("y = a < b < c;"), "y = a < b < c\n");
+        assert_eq!(generated("y = (a < b) < c;"), "y = (a < b) < c\n");
+    }
+
+    #[test]
+    fn nested_conditional_expressions() {
+        assert_eq!(generated("y = a ? b ? c : d : e;"), "y = (c if b else d) if a else e\n");
+        assert_eq!(generated("y = a ? b : c ? d : e;"), "y = b if a else d if c else e\n");
+        assert_eq!(generated("y = (a ? b : c) ? d : e;"), "y = d if (b if a else c) else e\n");
+    }
+
+    #[test]
+    fn attribute_target_of_conditional() {
+        assert_eq!(generated("(a if c else b).x = 1;"), "(a if c else b).x = 1\n");
+    }
+
+    #[test]
+    fn strings_keep_their_escapes_quotes_and_prefixes() {
+        assert_eq!(generated(r#"y = "a\n\"b\"\\";"#), "y = \"a\\n\\\"b\\\"\\\\\"\n");
+        assert_eq!(generated(r"y = 'it\'s';"), "y = 'it\\'s'\n");
+        assert_eq!(generated(r#"y = r"\d+" + b'\x00' + Rb"\n" + u"é";"#), "y = r\"\\d+\" + b'\\x00' + Rb\"\\n\" + u\"é\"\n");
+        assert_eq!(generated("y = \"\"\"a\n\"b\"\n\"\"\";"), "y = \"\"\"a\n\"b\"\n\"\"\"\n");
+        assert_eq!(generated("y = '''it's''';"), "y = '''it's'''\n");
+    }
+
+    #[test]
+    fn fstrings_keep_their_escapes_quotes_and_prefixes() {
+        assert_eq!(generated(r#"y = f"{{a}}\t{b!r:>{w}}";"#), "y = f\"{{a}}\\t{b!r:>{w}}\"\n");
+        assert_eq!(generated("y = f'{a[\"k\"]}';"), "y = f'{a[\"k\"]}'\n");
+        assert_eq!(generated(r#"y = rf"\d{n}" + Fr'{x}\w';"#), "y = rf\"\\d{n}\" + Fr'{x}\\w'\n");
+        assert_eq!(generated("y = f\"\"\"{a}\n\"{b}\"\n\"\"\";"), "y = f\"\"\"{a}\n\"{b}\"\n\"\"\"\n");
+        assert_eq!(generated("y = f\"{ {a} }\";"), "y = f\"{ {a}}\"\n");
+    }
+
+    #[test]
+    fn fstring_debug_fields_keep_their_text() {
+        assert_eq!(generated("y = f\"{a+b=}\";"), "y = f\"{a+b=}\"\n");
+        assert_eq!(generated("y = f\"{ x = }\";"), "y = f\"{ x = }\"\n");
+        assert_eq!(generated("y = f\"{x = !s:>10}\";"), "y = f\"{x = !s:>10}\"\n");
+        assert_eq!(generated("y = f\"{f(a, b)=:.2f}\";"), "y = f\"{f(a, b)=:.2f}\"\n");
+    }
+
+    #[test]
+    fn fstring_debug_fields_that_python_would_read_differently() {
+        assert_eq!(generated("y = f\"{a ? b : c=}\";"), "y = f\"a ? b : c={b if a else c!r}\"\n");
+        assert_eq!(generated("y = f\"{a ? b : c = :>10}\";"), "y = f\"a ? b : c = {b if a else c:>10}\"\n");
+        assert_eq!(generated("y = f\"{a ? b : c=!s}\";"), "y = f\"a ? b : c={b if a else c!s}\"\n");
+        assert_eq!(generated(r#"y = f"{g('\n')=}";"#), "y = f\"{g('\\n')=}\"\n");
+        assert_eq!(generated(r#"y = f"{a ? '\n' : "}"=}";"#), r#"y = f"a ? '\\n' : \"}}\"={'\n' if a else "}"!r}""#.to_string() + "\n");
+        assert_eq!(generated(r#"y = rf"{a ? "\d" : b=}";"#), r#"y = rf"a ? {'"'}\d{'"'} : b={"\d" if a else b!r}""#.to_string() + "\n");
+    }
+
+    #[test]
+    fn lambdas() {
+        assert_eq!(generated("f = lambda x, y: x + y;"), "f = lambda x, y: x + y\n");
+        assert_eq!(generated("f = lambda: 0;"), "f = lambda: 0\n");
+        assert_eq!(generated("f = (x, *args, y=1, **kw) => x;"), "f = lambda x, *args, y=1, **kw: x\n");
+        assert_eq!(generated("f = lambda x: lambda y: x + y;"), "f = lambda x: lambda y: x + y\n");
+    }
+
+    #[test]
+    fn lifted_lambdas() {
+        assert_eq!(
+            generated("ys = sorted(xs, key=(x) => { return -x; });"),
+            "def _lambda_1(x):\n    return -x\nys = sorted(xs, key=_lambda_1)\n",
+        );
+        // Annotations have no place in a `lambda`.
+        assert_eq!(generated("f = (x: int) => x;"), "def _lambda_1(x: int):\n    return x\nf = _lambda_1\n");
+        // A function lifted out of the body must stay within the parameters' scope.
+        assert_eq!(
+            generated("f = (x) => (y) => { return x + y; };"),
+            "def _lambda_2(x):\n    def _lambda_1(y):\n        return x + y\n    return _lambda_1\nf = _lambda_2\n",
+        );
+        // The names avoid anything already in the source.
+        assert_eq!(
+            generated("# not _lambda_1\nf = () => { return 1; };"),
+            "def __lambda_1():\n    return 1\nf = __lambda_1\n",
+        );
+    }
+
+    #[test]
+    fn lifted_lambdas_in_elif_conditions() {
+        assert_eq!(
+            generated("if a { f(); } else if b { g(); } else if c((x) => { return x; }) { h(); }"),
+            "def _lambda_1(x):\n    return x\nif a:\n    f()\nelif b:\n    g()\nelif c(_lambda_1):\n    h()\n",
+        );
+    }
+
+    #[test]
+    fn lifted_lambdas_in_comprehensions() {
+        // Lifted out of the comprehension, the function could not see `x`, so
+        // the comprehension becomes a generator function around it.
+        assert_eq!(
+            generated("ys = [((y) => { return y + x; })(1) for x in xs];"),
+            "def _lambda_2(_lambda_iterable):\n    for x in _lambda_iterable:\n        def _lambda_3(y):\n            \
+             return y + x\n        yield _lambda_3(1)\nys = list(_lambda_2(xs))\n",
+        );
+        assert_eq!(
+            generated("ys = [f(x) for x in xs if g((y) => { return y; })];"),
+            "def _lambda_2(_lambda_iterable):\n    for x in _lambda_iterable:\n        def _lambda_3(y):\n            \
+             return y\n        if g(_lambda_3):\n            yield f(x)\nys = list(_lambda_2(xs))\n",
+        );
+        assert_eq!(generated("ys = {k: (v) => v + 1 for k in ks};"), "ys = {k: lambda v: v + 1 for k in ks}\n");
+    }
+
+    #[test]
+    fn comprehensions() {
+        assert_eq!(generated("ys = [f(x) for x in xs if p(x)];"), "ys = [f(x) for x in xs if p(x)]\n");
+        assert_eq!(
+            generated("ys = [(x, y) for x in xs if x for y in ys if x < y if y];"),
+            "ys = [(x, y) for x in xs if x for y in ys if x < y if y]\n",
+        );
+        assert_eq!(generated("ys = {x for x in a ? b : c};"), "ys = {x for x in (b if a else c)}\n");
+        assert_eq!(
+            generated("ys = {k: v ? 1 : 2 for k, v in d.items()};"),
+            "ys = {k: 1 if v else 2 for k, v in d.items()}\n",
+        );
+        assert_eq!(generated("g = (x * x for x in xs);"), "g = (x * x for x in xs)\n");
+        assert_eq!(generated("ys = [lambda: x for x in xs];"), "ys = [lambda: x for x in xs]\n");
+    }
+
+    #[test]
+    fn generator_arguments() {
+        assert_eq!(generated("n = sum(x for x in xs);"), "n = sum(x for x in xs)\n");
+        assert_eq!(generated("n = f(x for x in xs for y in x);"), "n = f(x for x in xs for y in x)\n");
+        assert_eq!(generated("n = f((x for x in xs), 1);"), "n = f((x for x in xs), 1)\n");
+        assert_eq!(generated("n = f(*(x for x in xs));"), "n = f(*(x for x in xs))\n");
+    }
+
+    #[test]
+    fn comprehensions_lifted_into_generator_functions() {
+        // The first iterable is evaluated outside the comprehension, so a
+        // function lifted from it needs no generator function.
+        assert_eq!(
+            generated("ys = [x for x in map((v) => { return v; }, xs)];"),
+            "def _lambda_1(v):\n    return v\nys = [x for x in map(_lambda_1, xs)]\n",
+        );
+        assert_eq!(
+            generated("s = {x for x in xs if ((y) => { return y; })(x)};"),
+            "def _lambda_2(_lambda_iterable):\n    for x in _lambda_iterable:\n        def _lambda_3(y):\n            \
+             return y\n        if _lambda_3(x):\n            yield x\ns = set(_lambda_2(xs))\n",
+        );
+        assert_eq!(
+            generated("ys = {k: ((v) => { return v + k; })(1) for k in ks for j in ((v) => { return v; })(k)};"),
+            "def _lambda_3(_lambda_iterable):\n    for k in _lambda_iterable:\n        def _lambda_4(v):\n            \
+             return v\n        for j in _lambda_4(k):\n            def _lambda_5(v):\n                \
+             return v + k\n            yield k, _lambda_5(1)\nys = dict(_lambda_3(ks))\n",
+        );
+        assert_eq!(
+            generated("n = sum(((y) => { return x + y; })(1) for x in xs);"),
+            "def _lambda_2(_lambda_iterable):\n    for x in _lambda_iterable:\n        def _lambda_3(y):\n            \
+             return x + y\n        yield _lambda_3(1)\nn = sum(_lambda_2(xs))\n",
+        );
+    }
+
+    #[test]
+    fn delete_targets() {
+        assert_eq!(generated("del a, b.c, d[0];"), "del a, b.c, d[0]\n");
+        // Parentheses and brackets group targets, which are deleted one by one.
+        assert_eq!(generated("del (a), (b[1:2]);"), "del a, b[1:2]\n");
+        assert_eq!(generated("del (a, b);"), "del a, b\n");
+        assert_eq!(generated("del [a, b], (c, d.e);"), "del (a, b), (c, d.e)\n");
+        assert_eq!(generated("del ((a), [b]);"), "del a, (b,)\n");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn walrus() {
+        assert_eq!(generated("if n := len(a) {} else if m := 2 {}"), "if (n := len(a)):\n    pass\nelif (m := 2):\n    pass\n");
+        assert_eq!(generated("while k := f() {}"), "while (k := f()):\n    pass\n");
+        assert_eq!(generated("f(a := 1, b=(c := 2));"), "f((a := 1), b=(c := 2))\n");
+        assert_eq!(generated("ys = [y := f(x) for x in xs];"), "ys = [(y := f(x)) for x in xs]\n");
+        assert_eq!(generated("s = {y := 1, 2};"), "s = {(y := 1), 2}\n");
+        assert_eq!(generated("v = a[i := 1];"), "v = a[(i := 1)]\n");
+        assert_eq!(generated("n = sum(y := x for x in xs);"), "n = sum((y := x) for x in xs)\n");
+    }
+
+    #[test]
+    fn walrus_in_fstrings() {
+        // As in Python, a bare `:=` in a replacement field starts the format
+        // spec `=5`; the walrus needs parentheses.
+        assert_eq!(generated("y = f\"{x:=5}\";"), "y = f\"{x:=5}\"\n");
+        assert_eq!(generated("y = f\"{(x:=5)}\";"), "y = f\"{(x := 5)}\"\n");
+    }
+}
@@ -0,0 +1,280 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+use crate::ast::Span;
+
+/// A Bython source file together with the path it was read from.
+#[derive(Debug)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub text: String,
+}
+
+impl SourceFile {
+    pub fn new(path: impl Into<PathBuf>, text: impl Into<String>) -> Self {
+        SourceFile { path: path.into(), text: text.into() }
+    }
+
+    /// Returns the 1-based line and column (in characters) of a byte offset.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = self.clamp(offset);
+        let before = &self.text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = self.text[line_start..offset].chars().count() + 1;
+        (line, column)
+    }
+
+    /// Returns the text of a 1-based line without its line terminator.
+    pub fn line_text(&self, line: usize) -> &str {
+        self.text
+            .split('\n')
+            .nth(line.saturating_sub(1))
+            .map_or("", |l| l.strip_suffix('\r').unwrap_or(l))
+    }
+
+    /// Returns the span of the first token within `span` whose text is `token`,
+    /// such as a name used in a statement.
+    pub fn find_token(&self, span: Span, token: &str) -> Option<Span> {
+        let text = self.text.get(span.start..span.end)?;
+        tokens(text)
+            .find(|range| &text[range.clone()] == token)
+            .map(|range| Span { start: span.start + range.start, end: span.start + range.end })
+    }
+
+    fn clamp(&self, mut offset: usize) -> usize {
+        offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+}
+
+/// Splits Bython source text into the byte ranges of its tokens, skipping
+/// whitespace and comments. A string literal is one token, as is a run of
+/// identifier characters; any other character is a token on its own.
+pub fn tokens(text: &str) -> Tokens<'_> {
+    Tokens { text, position: 0 }
+}
+
+pub struct Tokens<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        loop {
+            let rest = &self.text[self.position..];
+            let c = rest.chars().next()?;
+            let len = match c {
+                ' ' | '\t' | '\r' | '\n' => {
+                    self.position += 1;
+                    continue;
+                }
+                '#' => {
+                    self.position += rest.find('\n').unwrap_or(rest.len());
+                    continue;
+                }
+                '"' | '\'' => string_len(rest, c),
+                c if is_identifier_char(c) => rest.find(|c| !is_identifier_char(c)).unwrap_or(rest.len()),
+                c => c.len_utf8(),
+            };
+            let start = self.position;
+            self.position += len;
+            return Some(start..self.position);
+        }
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c == '_' || c.is_alphanumeric()
+}
+
+/// Returns the length of the string literal that `text` starts with, or the
+/// length of `text` if the literal is never closed.
+fn string_len(text: &str, quote: char) -> usize {
+    let delimiter = if text.starts_with(&quote.to_string().repeat(3)) { 3 } else { 1 };
+    let closing = &text[..delimiter];
+    let mut chars = text.char_indices().skip(delimiter);
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if text[i..].starts_with(closing) {
+            return i + delimiter;
+        }
+    }
+    text.len()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    /// Reported, but does not stop the file from being transpiled.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// An error or warning tied to a location in a Bython source file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub path: PathBuf,
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Diagnostic {
+    pub fn new(source: &SourceFile, span: Span, message: impl Into<String>) -> Self {
+        let (line, column) = source.line_col(span.start);
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            path: source.path.clone(),
+            span,
+            line,
+            column,
+        }
+    }
+
+    pub fn warning(source: &SourceFile, span: Span, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Warning, ..Diagnostic::new(source, span, message) }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders a rustc-style report with the offending line and a caret underline.
+    pub fn render(&self, source: &SourceFile) -> String {
+        let line_text = source.line_text(self.line);
+        let gutter = " ".repeat(self.line.to_string().len());
+
+        let (end_line, end_column) = source.line_col(self.span.end);
+        let underline_len = if end_line == self.line && end_column > self.column {
+            end_column - self.column
+        } else if end_line > self.line {
+            (line_text.chars().count() + 1).saturating_sub(self.column).max(1)
+        } else {
+            1
+        };
+
+        let padding: String = line_text
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        format!(
+            "{}: {}\n{gutter}--> {}:{}:{}\n{gutter} |\n{} | {}\n{gutter} | {}{}\n",
+            self.severity,
+            self.message,
+            self.path.display(),
+            self.line,
+            self.column,
+            self.line,
+            line_text,
+            padding,
+            "^".repeat(underline_len),
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}: {}", self.path.display(), self.line, self.column, self.severity, self.message)
+    }
+}
+
+impl Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_col_counts_characters() {
+        let source = SourceFile::new("test.bython", "x = 1;\ny = \"é\" + z;\r\n");
+        assert_eq!(source.line_col(0), (1, 1));
+        assert_eq!(source.line_col(4), (1, 5));
+        assert_eq!(source.line_col(7), (2, 1));
+        // `é` is two bytes but one column.
+        assert_eq!(source.line_col(16), (2, 9));
+        // Offsets inside a character or past the end are clamped.
+        assert_eq!(source.line_col(13), (2, 6));
+        assert_eq!(source.line_col(100), (3, 1));
+        assert_eq!(source.line_text(2), "y = \"é\" + z;");
+    }
+
+    #[test]
+    fn render_underlines_the_span() {
+        let source = SourceFile::new("test.bython", "x = 1;\ny = foo + 2;\n");
+        let diagnostic = Diagnostic::new(&source, Span { start: 11, end: 14 }, "undefined name");
+        assert_eq!(
+            diagnostic.render(&source),
+            "error: undefined name\n \
+             --> test.bython:2:5\n  \
+             |\n\
+             2 | y = foo + 2;\n  \
+             |     ^^^\n",
+        );
+    }
+
+    #[test]
+    fn render_widens_the_gutter_and_keeps_tabs() {
+        let text = format!("{}\tx = ;\n", "\n".repeat(9));
+        let source = SourceFile::new("test.bython", text);
+        let diagnostic = Diagnostic::warning(&source, Span { start: 14, end: 15 }, "odd");
+        assert_eq!(
+            diagnostic.render(&source),
+            "warning: odd\n  \
+             --> test.bython:10:6\n   \
+             |\n\
+             10 | \tx = ;\n   \
+             | \t    ^\n",
+        );
+    }
+
+    #[test]
+    fn render_a_span_over_several_lines_to_the_end_of_the_first() {
+        let source = SourceFile::new("test.bython", "if x {\n    y;\n}\n");
+        let diagnostic = Diagnostic::new(&source, Span { start: 3, end: 16 }, "bad");
+        assert!(diagnostic.render(&source).ends_with("1 | if x {\n  |    ^^^\n"));
+    }
+
+    #[test]
+    fn render_an_empty_span_as_one_caret() {
+        let source = SourceFile::new("test.bython", "x = 1");
+        let diagnostic = Diagnostic::new(&source, Span { start: 5, end: 5 }, "expected `;`");
+        assert!(diagnostic.render(&source).ends_with("1 | x = 1\n  |      ^\n"));
+    }
+
+    #[test]
+    fn tokens_skip_whitespace_and_comments() {
+        let text = "a.b(\"# not a comment\", '''x''') # comment\n  ";
+        let tokens: Vec<_> = tokens(text).map(|range| &text[range]).collect();
+        assert_eq!(tokens, ["a", ".", "b", "(", "\"# not a comment\"", ",", "'''x'''", ")"]);
+    }
+
+    #[test]
+    fn find_token_matches_whole_tokens() {
+        let source = SourceFile::new("test.bython", "passes = 'pass' + pass;");
+        let span = Span { start: 0, end: source.text.len() };
+        assert_eq!(source.find_token(span, "pass"), Some(Span { start: 18, end: 22 }));
+        assert_eq!(source.find_token(span, "missing"), None);
+    }
+}
@@ -0,0 +1,178 @@
+use std::path::{Path, PathBuf};
+use crate::ast::{Block, Program, Spanned, Statement};
+
+/// Returns the `.bython` files that a program imports. Relative imports are
+/// resolved against the directory of the importing file, and absolute ones
+/// against the directory of `script`, the file that is run. Imports that do
+/// not name a Bython file, such as the standard library, are left for Python
+/// to resolve.
+pub fn imported_files(program: &Program, importer: &Path, script: &Path) -> Vec<PathBuf> {
+    let root = script.parent().unwrap_or(Path::new(""));
+    let mut files = Vec::new();
+    collect(&program.statements, importer, root, &mut files);
+    files
+}
+
+fn collect(statements: &[Spanned<Statement>], importer: &Path, root: &Path, files: &mut Vec<PathBuf>) {
+    for statement in statements {
+        match &statement.node {
+            Statement::Import { names } => {
+                for import in names {
+                    push_module(files, root.to_path_buf(), &import.name);
+                }
+            }
+            Statement::FromImport { module, level, names } => {
+                let base = package_dir(importer, root, *level);
+                let base = match module {
+                    Some(module) => {
+                        push_module(files, base.clone(), module);
+                        module_dir(base, module)
+                    }
+                    None => base,
+                };
+                // `from pkg import mod` may import a submodule rather than a name.
+                for import in names.iter().flatten() {
+                    push_module(files, base.clone(), &import.name);
+                }
+            }
+            Statement::If { consequence, elif_clauses, alternative, .. } => {
+                collect_block(consequence, importer, root, files);
+                for clause in elif_clauses {
+                    collect_block(&clause.consequence, importer, root, files);
+                }
+                if let Some(alternative) = alternative {
+                    collect_block(alternative, importer, root, files);
+                }
+            }
+            Statement::For { body, alternative, .. } => {
+                collect_block(body, importer, root, files);
+                if let Some(alternative) = alternative {
+                    collect_block(alternative, importer, root, files);
+                }
+            }
+            Statement::While { body, .. }
+            | Statement::With { body, .. }
+            | Statement::FunctionDef { body, .. }
+            | Statement::ClassDef { body, .. } => collect_block(body, importer, root, files),
+            Statement::Match { cases, .. } => {
+                for case in cases {
+                    collect_block(&case.body, importer, root, files);
+                }
+            }
+            Statement::Try { body, handlers, alternative, finally, .. } => {
+                collect_block(body, importer, root, files);
+                for handler in handlers {
+                    collect_block(&handler.body, importer, root, files);
+                }
+                for block in alternative.iter().chain(finally) {
+                    collect_block(block, importer, root, files);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Records the `.bython` files that importing `module` from `base` runs: the
+/// `__init__.bython` of each package along its path, then the module itself.
+fn push_module(files: &mut Vec<PathBuf>, base: PathBuf, module: &str) {
+    let mut path = base;
+    for part in module.split('.') {
+        path.push(part);
+        push_file(files, path.join("__init__.bython"));
+    }
+    push_file(files, path.with_extension("bython"));
+}
+
+fn push_file(files: &mut Vec<PathBuf>, file: PathBuf) {
+    if file.is_file() && !files.contains(&file) {
+        files.push(file);
+    }
+}
+
+fn module_dir(base: PathBuf, module: &str) -> PathBuf {
+    module.split('.').fold(base, |path, part| path.join(part))
+}
+
+fn collect_block(block: &Block, importer: &Path, root: &Path, files: &mut Vec<PathBuf>) {
+    collect(&block.statements, importer, root, files);
+}
+
+/// The directory that an import with `level` leading dots is relative to.
+/// Absolute imports are looked up in `root`, the directory of the script that
+/// is run, which is where Python finds them.
+fn package_dir(importer: &Path, root: &Path, level: usize) -> PathBuf {
+    if level == 0 {
+        return root.to_path_buf();
+    }
+    let mut dir = importer.parent().unwrap_or(Path::new("")).to_path_buf();
+    for _ in 1..level {
+        dir.pop();
+    }
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::diagnostic::SourceFile;
+    use crate::parser::parse_bython_code;
+
+    /// The files imported by `code` in the file `importer`, relative to `root`.
+    fn imports(root: &Path, importer: &str, code: &str) -> Vec<String> {
+        let source = SourceFile::new(root.join(importer), code);
+        let parsed = parse_bython_code(&source);
+        assert!(parsed.diagnostics.is_empty(), "`{}` did not parse", code);
+        imported_files(&parsed.program, &source.path, &root.join("main.bython"))
+            .iter()
+            .map(|file| file.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    #[test]
+    fn package_dirs() {
+        let root = Path::new("/project");
+        let importer = Path::new("/project/pkg/sub/mod.bython");
+        assert_eq!(package_dir(importer, root, 0), Path::new("/project"));
+        assert_eq!(package_dir(importer, root, 1), Path::new("/project/pkg/sub"));
+        assert_eq!(package_dir(importer, root, 2), Path::new("/project/pkg"));
+        assert_eq!(package_dir(importer, root, 3), Path::new("/project"));
+    }
+
+    #[test]
+    fn imported_bython_files() {
+        let root = std::env::temp_dir().join(format!("based_python-imports-{}", std::process::id()));
+        for file in [
+            "main.bython",
+            "helper.bython",
+            "pkg/__init__.bython",
+            "pkg/mod.bython",
+            "pkg/plain.py",
+            "pkg/sub/__init__.bython",
+            "pkg/sub/deep.bython",
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        assert_eq!(
+            imports(&root, "main.bython", "import os, pkg.sub.deep;\nfrom helper import x;"),
+            ["pkg/__init__.bython", "pkg/sub/__init__.bython", "pkg/sub/deep.bython", "helper.bython"],
+        );
+        assert_eq!(imports(&root, "main.bython", "import pkg;"), ["pkg/__init__.bython"]);
+        assert_eq!(
+            imports(&root, "main.bython", "fn f() {\n    if x {\n        import helper;\n    }\n}"),
+            ["helper.bython"],
+        );
+        assert_eq!(imports(&root, "pkg/__init__.bython", "from . import mod, plain;"), ["pkg/mod.bython"]);
+        assert_eq!(
+            imports(&root, "pkg/sub/deep.bython", "from .. import mod;\nfrom ..sub import deep;"),
+            ["pkg/mod.bython", "pkg/sub/__init__.bython", "pkg/sub/deep.bython"],
+        );
+        assert_eq!(imports(&root, "pkg/sub/deep.bython", "from ...helper import *;"), ["helper.bython"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
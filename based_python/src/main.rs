@@ -1,10 +1,17 @@
 mod parser;
 mod ast;
 mod codegen;
+mod diagnostic;
+mod imports;
+mod semantic;
 
-use std::{fs, io::{self, Write}, path::PathBuf};
+use std::{fs, io::{self, Write}, path::{Path, PathBuf}};
+use std::ffi::OsString;
+use std::collections::VecDeque;
 use std::process::Command;
 use clap::Parser;
+use codegen::{GeneratedCode, PythonVersion};
+use diagnostic::{Diagnostic, SourceFile};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -24,6 +31,10 @@ struct Args {
     /// Python interpreter to use (default: python)
     #[arg(long, default_value = "python")]
     python_interpreter: String,
+
+    /// Oldest Python version the generated code must run on, such as 3.9; constructs it lacks are errors
+    #[arg(long, default_value_t = PythonVersion::default())]
+    target_version: PythonVersion,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -32,56 +43,61 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let input_code = fs::read_to_string(&args.input)
         .map_err(|e| format!("Could not read input file {}: {}", args.input.display(), e))?;
 
-    let program_ast = parser::parse_bython_code(&input_code)?;
-
-    let python_code = codegen::generate_python_code(&program_ast);
+    let source = SourceFile::new(&args.input, input_code);
+    let (generated, imports) = transpile(&source, &args.input, args.target_version);
+    let python_code = &generated.python;
+    let modules = transpile_imports(&args.input, imports, args.target_version)?;
 
     match args.output {
         Some(output_path) => {
             fs::write(&output_path, python_code.as_bytes())
                 .map_err(|e| format!("Could not write to output file {}: {}", output_path.display(), e))?;
             println!("Successfully processed '{}' to '{}'", args.input.display(), output_path.display());
+            let output_dir = output_path.parent().unwrap_or(Path::new(""));
+            let module_paths = write_modules(&modules, &args.input, output_dir)?;
+            for (module, path) in modules.iter().zip(&module_paths) {
+                println!("Successfully processed '{}' to '{}'", module.source.path.display(), path.display());
+            }
 
             if args.run {
-                println!("Running generated Python code...");
-                println!("~~~ Output ~~~");
-                let output = Command::new(&args.python_interpreter)
-                    .arg(&output_path)
-                    .output()
-                    .map_err(|e| format!("Failed to execute Python interpreter '{}': {}", args.python_interpreter, e))?;
-
-                io::stdout().write_all(&output.stdout)?;
-                io::stderr().write_all(&output.stderr)?;
-
-                if !output.status.success() {
-                    return Err(format!("Python execution failed with exit code: {}",
-                                       output.status.code().unwrap_or(-1)).into());
-                }
+                let locate = |file: &str| {
+                    if same_file(Path::new(file), &output_path) {
+                        return Some((&generated, &source));
+                    }
+                    locate_module(file, &modules, &module_paths)
+                };
+                run_python(&args.python_interpreter, &output_path, None, locate)?;
             }
         }
         None => {
             if args.run {
-                println!("Running generated Python code...");
-                println!("~~~ Output ~~~");
-                let mut child = Command::new(&args.python_interpreter)
-                    .stdin(std::process::Stdio::piped())
-                    .stdout(std::process::Stdio::piped())
-                    .stderr(std::process::Stdio::piped())
-                    .spawn()
-                    .map_err(|e| format!("Failed to start Python interpreter '{}': {}", args.python_interpreter, e))?;
-
-                if let Some(stdin) = child.stdin.as_mut() {
-                    stdin.write_all(python_code.as_bytes())?;
-                }
+                // The program is written next to its modules in a temporary
+                // directory and run from there, so that they come first on
+                // Python's path, ahead of the input's own directory.
+                let temp_dir = TempDir(std::env::temp_dir().join(format!("based_python-{}", std::process::id())));
+                let module_paths = write_modules(&modules, &args.input, &temp_dir.0)?;
+                let script_name = args.input.file_stem().unwrap_or("main".as_ref());
+                let script_path = temp_dir.0.join(script_name).with_extension("py");
+                fs::write(&script_path, python_code.as_bytes())
+                    .map_err(|e| format!("Could not write to output file {}: {}", script_path.display(), e))?;
 
-                let output = child.wait_with_output()?;
-                io::stdout().write_all(&output.stdout)?;
-                io::stderr().write_all(&output.stderr)?;
-
-                if !output.status.success() {
-                    return Err(format!("Python execution failed with exit code: {}",
-                                       output.status.code().unwrap_or(-1)).into());
-                }
+                // Plain Python modules next to the input can still be imported.
+                let input_dir = fs::canonicalize(&args.input)?.parent().map(Path::to_path_buf).unwrap_or_default();
+                let python_path = match std::env::var_os("PYTHONPATH") {
+                    Some(existing) => {
+                        let mut paths = vec![input_dir];
+                        paths.extend(std::env::split_paths(&existing));
+                        std::env::join_paths(paths)?
+                    }
+                    None => input_dir.into_os_string(),
+                };
+                let locate = |file: &str| {
+                    if same_file(Path::new(file), &script_path) {
+                        return Some((&generated, &source));
+                    }
+                    locate_module(file, &modules, &module_paths)
+                };
+                run_python(&args.python_interpreter, &script_path, Some(python_path), locate)?;
             } else {
                 io::stdout().write_all(python_code.as_bytes())?;
             }
@@ -90,3 +106,310 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Runs a generated Python script, passing its output through with traceback
+/// frames rewritten to point at the Bython source; see `rewrite_traceback`.
+fn run_python<'a>(
+    interpreter: &str,
+    script: &Path,
+    python_path: Option<OsString>,
+    locate: impl Fn(&str) -> Option<(&'a GeneratedCode, &'a SourceFile)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Running generated Python code...");
+    println!("~~~ Output ~~~");
+    let mut command = Command::new(interpreter);
+    command.arg(script);
+    if let Some(python_path) = python_path {
+        command.env("PYTHONPATH", python_path);
+    }
+    let output = command
+        .output()
+        .map_err(|e| format!("Failed to execute Python interpreter '{}': {}", interpreter, e))?;
+
+    io::stdout().write_all(&output.stdout)?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    io::stderr().write_all(rewrite_traceback(&stderr, locate).as_bytes())?;
+
+    if !output.status.success() {
+        return Err(format!("Python execution failed with exit code: {}",
+                           output.status.code().unwrap_or(-1)).into());
+    }
+    Ok(())
+}
+
+/// A temporary directory, removed with everything in it when dropped.
+struct TempDir(PathBuf);
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A transpiled Bython module imported by the main program.
+struct Module {
+    source: SourceFile,
+    generated: GeneratedCode,
+}
+
+/// Parses and generates Python for one file, also returning the `.bython`
+/// files it imports, with absolute imports found next to `script`, the file
+/// that is run. Exits with the diagnostics if the file has errors.
+fn transpile(source: &SourceFile, script: &Path, target: PythonVersion) -> (GeneratedCode, Vec<PathBuf>) {
+    let parsed = parser::parse_bython_code(source);
+    if !parsed.diagnostics.is_empty() {
+        report(&parsed.diagnostics, source);
+    }
+    let (errors, warnings): (Vec<_>, Vec<_>) = semantic::check_program(&parsed.program, source)
+        .into_iter()
+        .partition(Diagnostic::is_error);
+    for warning in &warnings {
+        eprintln!("{}", warning.render(source));
+    }
+    if !errors.is_empty() {
+        report(&errors, source);
+    }
+    let generated = codegen::generate_python_code(&parsed.program, source, target)
+        .unwrap_or_else(|diagnostic| report(&[diagnostic], source));
+    let imports = imports::imported_files(&parsed.program, &source.path, script);
+    (generated, imports)
+}
+
+/// Transpiles every `.bython` module reachable through imports, once each.
+fn transpile_imports(input: &Path, imports: Vec<PathBuf>, target: PythonVersion) -> Result<Vec<Module>, Box<dyn std::error::Error>> {
+    let mut seen = vec![fs::canonicalize(input)?];
+    let mut queue = VecDeque::from(imports);
+    let mut modules = Vec::new();
+
+    while let Some(path) = queue.pop_front() {
+        let canonical = fs::canonicalize(&path)?;
+        if seen.contains(&canonical) {
+            continue;
+        }
+        seen.push(canonical);
+
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read imported module {}: {}", path.display(), e))?;
+        let source = SourceFile::new(path, text);
+        let (generated, imports) = transpile(&source, input, target);
+        queue.extend(imports);
+        modules.push(Module { source, generated });
+    }
+    Ok(modules)
+}
+
+/// Writes each module's Python to `output_dir`, at the same path relative to
+/// it as the module has relative to the input file. The other files in the
+/// packages that modules are in are copied along with them.
+fn write_modules(modules: &[Module], input: &Path, output_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let input_dir = fs::canonicalize(input)?.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut relatives = Vec::new();
+    for module in modules {
+        let module_path = fs::canonicalize(&module.source.path)?;
+        let relative = module_path.strip_prefix(&input_dir).map_err(|_| {
+            format!("Imported module {} is outside the directory of {}", module.source.path.display(), input.display())
+        })?;
+        relatives.push(relative.to_path_buf());
+    }
+
+    // Packages may hold plain Python modules and data files as well. Copy them
+    // first, so that generated modules replace any stale `.py` files.
+    fs::create_dir_all(output_dir)?;
+    if fs::canonicalize(output_dir)? != input_dir {
+        let mut packages: Vec<&Path> = Vec::new();
+        for relative in &relatives {
+            let mut components = relative.components();
+            if let (Some(package), Some(_)) = (components.next(), components.next()) {
+                let package = Path::new(package.as_os_str());
+                if !packages.contains(&package) {
+                    copy_package(&input_dir.join(package), &output_dir.join(package))?;
+                    packages.push(package);
+                }
+            }
+        }
+    }
+
+    let mut paths = Vec::new();
+    for (module, relative) in modules.iter().zip(&relatives) {
+        let path = output_dir.join(relative).with_extension("py");
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, module.generated.python.as_bytes())
+            .map_err(|e| format!("Could not write to output file {}: {}", path.display(), e))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Copies a package directory, subpackages included, leaving out the Bython
+/// sources and Python's bytecode caches.
+fn copy_package(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name() else { continue };
+        if path.is_dir() {
+            if name != "__pycache__" {
+                copy_package(&path, &to.join(name))?;
+            }
+        } else if path.extension().is_none_or(|extension| extension != "bython") {
+            fs::copy(&path, to.join(name))?;
+        }
+    }
+    Ok(())
+}
+
+fn locate_module<'a>(file: &str, modules: &'a [Module], paths: &[PathBuf]) -> Option<(&'a GeneratedCode, &'a SourceFile)> {
+    modules.iter()
+        .zip(paths)
+        .find(|(_, path)| same_file(Path::new(file), path))
+        .map(|(module, _)| (&module.generated, &module.source))
+}
+
+/// Rewrites Python traceback frames that point into generated code so they
+/// show the Bython file, line and source text instead. `locate` returns the
+/// generated code and source for a file named in a frame, if it is one of ours.
+fn rewrite_traceback<'a>(
+    stderr: &str,
+    locate: impl Fn(&str) -> Option<(&'a GeneratedCode, &'a SourceFile)>,
+) -> String {
+    let mut rewritten = String::new();
+    let mut lines = stderr.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let Some((file, python_line, rest)) = parse_traceback_frame(line) else {
+            rewritten.push_str(line);
+            rewritten.push('\n');
+            continue;
+        };
+        let Some((generated, source)) = locate(file) else {
+            rewritten.push_str(line);
+            rewritten.push('\n');
+            continue;
+        };
+        let Some(span) = generated.source_map.lookup(python_line) else {
+            rewritten.push_str(line);
+            rewritten.push('\n');
+            continue;
+        };
+
+        let (bython_line, _) = source.line_col(span.start);
+        rewritten.push_str(&format!("  File \"{}\", line {}{}\n", source.path.display(), bython_line, rest));
+        rewritten.push_str(&format!("    {}\n", source.line_text(bython_line).trim()));
+
+        // Drop Python's own source line and any caret markers under it: their
+        // columns refer to the generated code.
+        while let Some(next) = lines.peek() {
+            if next.starts_with("    ") && !next.trim_start().starts_with("File \"") {
+                lines.next();
+            } else {
+                break;
+            }
+        }
+    }
+    rewritten
+}
+
+/// Splits `  File "path", line 12, in func` into the path, line number and the
+/// text after the line number.
+fn parse_traceback_frame(line: &str) -> Option<(&str, usize, &str)> {
+    let rest = line.strip_prefix("  File \"")?;
+    let (file, rest) = rest.split_once("\", line ")?;
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let python_line = rest[..digits].parse().ok()?;
+    Some((file, python_line, &rest[digits..]))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Prints diagnostics with their source snippets and exits with a failure status.
+fn report(diagnostics: &[Diagnostic], source: &SourceFile) -> ! {
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic.render(source));
+    }
+    match diagnostics.len() {
+        1 => eprintln!("error: aborting due to previous error"),
+        n => eprintln!("error: aborting due to {} previous errors", n),
+    }
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(source: &SourceFile) -> GeneratedCode {
+        let parsed = parser::parse_bython_code(source);
+        assert!(parsed.diagnostics.is_empty());
+        codegen::generate_python_code(&parsed.program, source, PythonVersion::default()).unwrap()
+    }
+
+    #[test]
+    fn parse_frames() {
+        assert_eq!(
+            parse_traceback_frame("  File \"<stdin>\", line 12, in f"),
+            Some(("<stdin>", 12, ", in f")),
+        );
+        assert_eq!(parse_traceback_frame("  File \"a, b.py\", line 3"), Some(("a, b.py", 3, "")));
+        assert_eq!(parse_traceback_frame("    x = 1"), None);
+        assert_eq!(parse_traceback_frame("  File \"a.py\", line x, in f"), None);
+        assert_eq!(parse_traceback_frame("Traceback (most recent call last):"), None);
+    }
+
+    #[test]
+    fn source_map_lookup() {
+        let source = SourceFile::new("test.bython", "x = 1;\nif x {\n    y = 2;\n}\n");
+        let generated = generate(&source);
+        assert_eq!(generated.source_map.lookup(0), None);
+        assert_eq!(generated.source_map.lookup(1).map(|span| source.line_col(span.start)), Some((1, 1)));
+        assert_eq!(generated.source_map.lookup(3).map(|span| source.line_col(span.start)), Some((3, 5)));
+        assert_eq!(generated.source_map.lookup(4), None);
+    }
+
+    #[test]
+    fn rewrite_frames_in_generated_code() {
+        let main = SourceFile::new("main.bython", "import helper;\n\nhelper.f(0);\n");
+        let main_generated = generate(&main);
+        let helper = SourceFile::new("helper.bython", "fn f(x) {\n    return 1 / x;\n}\n");
+        let helper_generated = generate(&helper);
+        let locate = |file: &str| match file {
+            "<stdin>" => Some((&main_generated, &main)),
+            "/tmp/out/helper.py" => Some((&helper_generated, &helper)),
+            _ => None,
+        };
+
+        let stderr = "\
+Traceback (most recent call last):
+  File \"<stdin>\", line 2, in <module>
+    helper.f(0)
+  File \"/tmp/out/helper.py\", line 2, in f
+    return 1 / x
+           ~~^~~
+  File \"/usr/lib/python3.12/other.py\", line 7, in g
+    h()
+    ^^^
+  File \"<stdin>\", line 40, in <module>
+    unknown()
+ZeroDivisionError: division by zero
+";
+        assert_eq!(rewrite_traceback(stderr, locate), "\
+Traceback (most recent call last):
+  File \"main.bython\", line 3, in <module>
+    helper.f(0);
+  File \"helper.bython\", line 2, in f
+    return 1 / x;
+  File \"/usr/lib/python3.12/other.py\", line 7, in g
+    h()
+    ^^^
+  File \"<stdin>\", line 40, in <module>
+    unknown()
+ZeroDivisionError: division by zero
+");
+    }
+}
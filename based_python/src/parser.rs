@@ -1,6 +1,8 @@
 use pest::Parser;
+use pest::error::{ErrorVariant, InputLocation};
 use pest::iterators::{Pair, Pairs};
-use crate::ast::{Program, Statement, Expression, Block, Operator};
+use crate::ast::{Program, Statement, Expression, Argument, Block, Comprehension, ElifClause, ExceptHandler, FStringPart, ImportAlias, LambdaBody, MatchCase, Operator, Parameter, ParameterKind, Pattern, Span, Spanned, Target, UnaryOperator, WithItem};
+use crate::diagnostic::{tokens, Diagnostic, SourceFile};
 use std::error::Error;
 use std::fmt;
 use pest_derive::Parser;
@@ -15,6 +17,37 @@ pub struct BythonParser;
 #[derive(Debug)]
 pub struct BythonParseError {
     message: String,
+    span: Span,
+}
+
+impl BythonParseError {
+    fn new(message: impl Into<String>, span: Span) -> Self {
+        BythonParseError { message: message.into(), span }
+    }
+
+    fn at(message: impl Into<String>, pair: &Pair<Rule>) -> Self {
+        BythonParseError::new(message, pair.as_span().into())
+    }
+
+    fn into_diagnostic(self, source: &SourceFile) -> Diagnostic {
+        Diagnostic::new(source, self.span, self.message)
+    }
+}
+
+impl From<pest::Span<'_>> for Span {
+    /// Leaves out the whitespace and comments that pest skips before trying an
+    /// optional or repeated part at the end of a rule, so that `1 = x;` points
+    /// at `1` and not `1 `.
+    fn from(span: pest::Span<'_>) -> Self {
+        let text = span.as_str().trim_end_matches([' ', '\t', '\r', '\n']);
+        let last_line = &text[text.rfind('\n').map_or(0, |i| i + 1)..];
+        // Only a `#` on the last line can start a trailing comment.
+        let len = match last_line.contains('#') {
+            true => tokens(text).last().map_or(0, |token| token.end),
+            false => text.len(),
+        };
+        Span { start: span.start(), end: span.start() + len }
+    }
 }
 
 impl fmt::Display for BythonParseError {
@@ -26,423 +59,1532 @@ impl fmt::Display for BythonParseError {
 impl Error for BythonParseError {}
 
 lazy_static! {
+    /// Python's operator precedence, loosest binding first.
     static ref PRATT_PARSER: PrattParser<Rule> = {
         PrattParser::new()
-            .op(Op::infix(Rule::eq, Assoc::Left))
-            .op(Op::infix(Rule::neq, Assoc::Left))
-            .op(Op::infix(Rule::gt, Assoc::Left))
-            .op(Op::infix(Rule::lt, Assoc::Left))
-            .op(Op::infix(Rule::gte, Assoc::Left))
-            .op(Op::infix(Rule::lte, Assoc::Left))
-
-            .op(Op::infix(Rule::add, Assoc::Left))
-            .op(Op::infix(Rule::subtract, Assoc::Left))
-
-            .op(Op::infix(Rule::multiply, Assoc::Left))
-            .op(Op::infix(Rule::divide, Assoc::Left))
+            .op(Op::infix(Rule::conditional_if, Assoc::Right) | Op::infix(Rule::conditional_question, Assoc::Right))
+            .op(Op::infix(Rule::or_op, Assoc::Left))
+            .op(Op::infix(Rule::and_op, Assoc::Left))
+            .op(Op::prefix(Rule::not_op))
+            .op(Op::infix(Rule::eq, Assoc::Left) | Op::infix(Rule::neq, Assoc::Left)
+                | Op::infix(Rule::gt, Assoc::Left) | Op::infix(Rule::lt, Assoc::Left)
+                | Op::infix(Rule::gte, Assoc::Left) | Op::infix(Rule::lte, Assoc::Left)
+                | Op::infix(Rule::in_op, Assoc::Left) | Op::infix(Rule::not_in_op, Assoc::Left)
+                | Op::infix(Rule::is_op, Assoc::Left) | Op::infix(Rule::is_not_op, Assoc::Left))
+            .op(Op::infix(Rule::bit_or, Assoc::Left))
+            .op(Op::infix(Rule::bit_xor, Assoc::Left))
+            .op(Op::infix(Rule::bit_and, Assoc::Left))
+            .op(Op::infix(Rule::left_shift, Assoc::Left) | Op::infix(Rule::right_shift, Assoc::Left))
+            .op(Op::infix(Rule::add, Assoc::Left) | Op::infix(Rule::subtract, Assoc::Left))
+            .op(Op::infix(Rule::multiply, Assoc::Left) | Op::infix(Rule::matmul, Assoc::Left)
+                | Op::infix(Rule::divide, Assoc::Left) | Op::infix(Rule::floor_divide, Assoc::Left)
+                | Op::infix(Rule::modulo, Assoc::Left))
+            .op(Op::prefix(Rule::negate) | Op::prefix(Rule::unary_plus) | Op::prefix(Rule::invert))
+            .op(Op::infix(Rule::power, Assoc::Right))
     };
 }
 
-/// Parses the input string and returns the AST.
-pub fn parse_bython_code(input: &str) -> Result<Program, BythonParseError> {
-    let parse_result = BythonParser::parse(Rule::program, input);
+/// The result of parsing a file: the statements that parsed successfully and
+/// a diagnostic for every statement that did not.
+#[derive(Debug)]
+pub struct ParseOutput {
+    pub program: Program,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Parses the source file and returns the AST along with any errors.
+pub fn parse_bython_code(source: &SourceFile) -> ParseOutput {
+    pest::set_error_detail(true);
+    let pairs = match BythonParser::parse(Rule::program, &source.text) {
+        Ok(pairs) => pairs,
+        Err(e) => {
+            let diagnostic = Diagnostic::new(source, pest_error_span(&e), pest_error_message(e));
+            return ParseOutput {
+                program: Program { statements: Vec::new() },
+                diagnostics: vec![diagnostic],
+            };
+        }
+    };
 
-    match parse_result {
-        Ok(pairs) => {
-            let mut statements = Vec::new();
-            for pair in pairs {
-                if pair.as_rule() == Rule::program {
-                    for inner_pair in pair.into_inner() {
-                        if inner_pair.as_rule() == Rule::statement {
-                            statements.push(parse_statement(inner_pair)?);
-                        }
-                    }
+    let mut errors = Vec::new();
+    let mut statements = Vec::new();
+    for pair in pairs {
+        if pair.as_rule() == Rule::program {
+            statements.extend(parse_statements(pair.into_inner(), &mut errors));
+        }
+    }
+
+    errors.sort_by_key(|e| e.span.start);
+    ParseOutput {
+        program: Program { statements },
+        diagnostics: errors.into_iter().map(|e| e.into_diagnostic(source)).collect(),
+    }
+}
+
+fn pest_error_span(error: &pest::error::Error<Rule>) -> Span {
+    match error.location {
+        InputLocation::Pos(pos) => Span { start: pos, end: pos },
+        InputLocation::Span((start, end)) => Span { start, end },
+    }
+}
+
+/// Literal tokens worth naming in "expected ..." messages.
+const EXPECTED_DELIMITERS: &[&str] = &[";", ",", ":", "=", ")", "]", "}", "{"];
+
+fn pest_error_message(error: pest::error::Error<Rule>) -> String {
+    let mut expected: Vec<String> = Vec::new();
+    if let ErrorVariant::ParsingError { positives, .. } = &error.variant {
+        for rule in positives {
+            let name = describe_rule(rule).to_string();
+            if !expected.contains(&name) {
+                expected.push(name);
+            }
+        }
+    }
+    // Delimiters such as `;` or `)` are not rules, so they only show up in the
+    // parse attempts collected when error detail is enabled.
+    if let Some(attempts) = error.parse_attempts() {
+        for token in attempts.expected_tokens() {
+            let token = token.to_string();
+            if !EXPECTED_DELIMITERS.contains(&token.as_str()) {
+                continue;
+            }
+            let token = format!("`{}`", token);
+            if !expected.contains(&token) {
+                expected.push(token);
+            }
+        }
+    }
+
+    match expected.as_slice() {
+        [] => error.variant.message().into_owned(),
+        [only] => format!("expected {}", only),
+        [init @ .., last] => format!("expected {} or {}", init.join(", "), last),
+    }
+}
+
+/// Human-readable name of a grammar rule for "expected ..." messages.
+fn describe_rule(rule: &Rule) -> &'static str {
+    match rule {
+        Rule::EOI => "end of input",
+        Rule::statement | Rule::assignment_statement | Rule::augmented_assignment_statement
+        | Rule::annotated_assignment_statement | Rule::expression_statement
+        | Rule::return_statement | Rule::if_statement | Rule::for_statement
+        | Rule::while_statement | Rule::function_def | Rule::class_def
+        | Rule::break_statement | Rule::continue_statement | Rule::try_statement
+        | Rule::raise_statement | Rule::with_statement | Rule::import_statement
+        | Rule::from_import_statement | Rule::match_statement | Rule::pass_statement
+        | Rule::del_statement | Rule::assert_statement | Rule::global_statement
+        | Rule::nonlocal_statement => "statement",
+        Rule::dotted_name | Rule::relative_module => "module name",
+        Rule::import_alias | Rule::import_names | Rule::name_alias => "name",
+        Rule::import_star => "`*`",
+        Rule::KEYWORD_IMPORT => "`import`",
+        Rule::target_list | Rule::starred_target => "loop target",
+        Rule::tuple_comma => "`,`",
+        Rule::assignment_targets | Rule::starred_assignment_target => "assignment target",
+        Rule::assignment_value => "expression",
+        Rule::augmented_operator => "operator",
+        Rule::named_expression => "expression",
+        Rule::KEYWORD_IN => "`in`",
+        Rule::expression | Rule::primary | Rule::parenthesised | Rule::bracketed | Rule::braced => "expression",
+        Rule::dict_entry => "dict entry",
+        Rule::dict_value => "`:`",
+        Rule::call => "`(`",
+        Rule::attribute => "`.`",
+        Rule::subscript => "`[`",
+        Rule::subscript_item => "expression",
+        Rule::slice | Rule::slice_upper | Rule::slice_step => "slice",
+        Rule::block => "`{`",
+        Rule::ident | Rule::dunder_ident => "identifier",
+        Rule::integer | Rule::float | Rule::imaginary => "number",
+        Rule::string | Rule::fstring | Rule::fstring_text | Rule::fstring_quote => "string",
+        Rule::replacement_field | Rule::format_spec | Rule::format_spec_text => "replacement field",
+        Rule::fstring_conversion => "`!r`, `!s` or `!a`",
+        Rule::keyword_argument | Rule::starred_argument | Rule::double_starred_argument => "argument",
+        Rule::parameters | Rule::named_parameter | Rule::var_positional_parameter
+        | Rule::var_keyword_parameter | Rule::keyword_only_marker
+        | Rule::positional_only_marker => "parameter",
+        Rule::decorator => "decorator",
+        Rule::yield_expression | Rule::yield_value => "expression",
+        Rule::KEYWORD_RETURN => "`return`",
+        Rule::KEYWORD_YIELD => "`yield`",
+        Rule::with_item => "context manager",
+        Rule::KEYWORD_WITH => "`with`",
+        Rule::await_expression => "expression",
+        Rule::KEYWORD_ASYNC => "`async`",
+        Rule::KEYWORD_AWAIT => "`await`",
+        Rule::KEYWORD_MATCH => "`match`",
+        Rule::KEYWORD_PASS => "`pass`",
+        Rule::KEYWORD_DEL => "`del`",
+        Rule::KEYWORD_ASSERT => "`assert`",
+        Rule::KEYWORD_GLOBAL => "`global`",
+        Rule::KEYWORD_NONLOCAL => "`nonlocal`",
+        Rule::KEYWORD_CASE | Rule::case_clause => "`case`",
+        Rule::guard => "`if`",
+        Rule::pattern | Rule::or_pattern | Rule::literal_pattern | Rule::pattern_constant
+        | Rule::class_pattern | Rule::keyword_pattern | Rule::value_pattern | Rule::wildcard_pattern
+        | Rule::capture_pattern | Rule::sequence_pattern | Rule::star_pattern
+        | Rule::mapping_pattern | Rule::mapping_item | Rule::double_star_pattern => "pattern",
+        Rule::KEYWORD_FOR | Rule::comprehension_for => "`for`",
+        Rule::KEYWORD_IF | Rule::comprehension_if => "`if`",
+        Rule::lambda_expression | Rule::arrow_function => "expression",
+        Rule::lambda_parameters | Rule::lambda_named_parameter
+        | Rule::lambda_var_positional_parameter | Rule::lambda_var_keyword_parameter => "parameter",
+        Rule::KEYWORD_LAMBDA => "`lambda`",
+        Rule::class_arguments => "base class list",
+        Rule::annotation => "`:`",
+        Rule::default_value => "`=`",
+        Rule::return_annotation => "`->`",
+        Rule::add | Rule::subtract | Rule::multiply | Rule::divide | Rule::floor_divide
+        | Rule::modulo | Rule::power | Rule::matmul | Rule::left_shift | Rule::right_shift
+        | Rule::bit_and | Rule::bit_or | Rule::bit_xor
+        | Rule::eq | Rule::neq | Rule::gt | Rule::lt | Rule::gte | Rule::lte
+        | Rule::in_op | Rule::not_in_op | Rule::is_op | Rule::is_not_op
+        | Rule::and_op | Rule::or_op | Rule::conditional_if | Rule::conditional_question => "operator",
+        Rule::not_op | Rule::negate | Rule::unary_plus | Rule::invert => "expression",
+        Rule::KEYWORD_ELSE | Rule::elif_clause => "`else`",
+        Rule::KEYWORD_EXCEPT | Rule::except_clause => "`except`",
+        Rule::except_star => "`*`",
+        Rule::KEYWORD_FINALLY | Rule::finally_clause => "`finally`",
+        Rule::KEYWORD_FROM => "`from`",
+        Rule::KEYWORD_AS => "`as`",
+        _ => "token",
+    }
+}
+
+/// Takes the next child of a pair, reporting the parent's span if it is missing.
+fn next_pair<'i>(inner: &mut Pairs<'i, Rule>, parent: Span, expected: &str) -> Result<Pair<'i, Rule>, BythonParseError> {
+    inner.next().ok_or_else(|| BythonParseError::new(format!("expected {}", expected), parent))
+}
+
+/// Parses the statements of a block or program, recording errors instead of
+/// stopping at the first one so that every problem is reported in one pass.
+fn parse_statements(pairs: Pairs<Rule>, errors: &mut Vec<BythonParseError>) -> Vec<Spanned<Statement>> {
+    let mut statements = Vec::new();
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::statement => {
+                let span = pair.as_span().into();
+                match parse_statement(pair, errors) {
+                    Ok(node) => statements.push(Spanned { node, span }),
+                    Err(e) => errors.push(e),
+                }
+            }
+            Rule::invalid_statement => errors.push(describe_invalid_statement(&pair)),
+            Rule::unmatched_brace => {
+                let message = if pair.as_str() == "{" { "unclosed delimiter `{`" } else { "unexpected closing delimiter `}`" };
+                let error = BythonParseError::at(message, &pair);
+                // A header cut short by an unclosed block is reported at the
+                // same place; the delimiter is the more useful explanation.
+                if errors.last().is_some_and(|last| last.span.start == error.span.start) {
+                    errors.pop();
                 }
+                errors.push(error);
             }
-            Ok(Program { statements })
+            _ => {}
         }
-        Err(e) => Err(BythonParseError {
-            message: format!("Parsing error: {}", e),
-        }),
     }
+    statements
 }
 
-fn parse_statement(pair: Pair<Rule>) -> Result<Statement, BythonParseError> {
+/// Re-parses text skipped by error recovery on its own to find out why it is
+/// not a valid statement.
+fn describe_invalid_statement(pair: &Pair<Rule>) -> BythonParseError {
+    let offset = pair.as_span().start();
+    match BythonParser::parse(Rule::single_statement, pair.as_str()) {
+        Err(e) => {
+            let span = pest_error_span(&e);
+            BythonParseError::new(
+                pest_error_message(e),
+                Span { start: span.start + offset, end: span.end + offset },
+            )
+        }
+        Ok(_) => BythonParseError::at("invalid statement", pair),
+    }
+}
+
+fn parse_statement(pair: Pair<Rule>, errors: &mut Vec<BythonParseError>) -> Result<Statement, BythonParseError> {
+    let span: Span = pair.as_span().into();
     match pair.as_rule() {
         Rule::assignment_statement => {
-            let mut inner = pair.into_inner();
-            let target = inner.next().unwrap();
-            let value_pair = inner.next().unwrap();
-            
-            let value = match value_pair.as_rule() {
-                Rule::function_call_stmt => {
-                    let func_call_inner = value_pair.into_inner().next().unwrap();
-                    parse_expression(func_call_inner)?
-                },
-                Rule::term => parse_expression(value_pair)?,
-                _ => parse_expression(value_pair)?
-            };
-
-            match target.as_rule() {
-                Rule::ident => Ok(Statement::Assignment {
-                    name: target.as_str().to_string(),
-                    value
-                }),
-                Rule::member_access => {
-                    let mut parts = target.into_inner();
-                    let object = parts.next().unwrap().as_str().to_string();
-                    let member = parts.next().unwrap().as_str().to_string();
-                    println!("MemberAcess {:?}", member);
-                    Ok(Statement::Assignment {
-                        name: format!("{}.{}", object, member),
-                        value
-                    })
-                },
-                _ => Err(BythonParseError {
-                    message: format!("Invalid assignment target: {:?}", target.as_rule())
-                })
+            let mut targets = Vec::new();
+            let mut value = None;
+            for part in pair.into_inner() {
+                match part.as_rule() {
+                    Rule::assignment_value => value = Some(parse_expression_tuple(part)?),
+                    _ => {
+                        let target_span: Span = part.as_span().into();
+                        let target = parse_target(part)?;
+                        check_unpacking(&target, target_span)?;
+                        targets.push(target);
+                    }
+                }
             }
+            let value = value.ok_or_else(|| BythonParseError::new("expected assigned value", span))?;
+            Ok(Statement::Assignment { targets, value })
         }
-        Rule::print_statement => {
+        Rule::augmented_assignment_statement => {
             let mut inner = pair.into_inner();
-            let content_pair = inner.next().unwrap();
-            let content = parse_expression(content_pair.into_inner().next().unwrap())?;
-            Ok(Statement::Print { content })
+            let target = parse_target(next_pair(&mut inner, span, "assignment target")?)?;
+            let operator = parse_augmented_operator(next_pair(&mut inner, span, "operator")?)?;
+            let value = parse_expression_tuple(next_pair(&mut inner, span, "assigned value")?)?;
+            Ok(Statement::AugmentedAssignment { target, operator, value })
+        }
+        Rule::annotated_assignment_statement => {
+            let mut inner = pair.into_inner();
+            let target = parse_target(next_pair(&mut inner, span, "assignment target")?)?;
+            let annotation = parse_expression(next_pair(&mut inner, span, "annotation")?)?;
+            let value = match inner.next() {
+                Some(value) => Some(parse_expression_tuple(value)?),
+                None => None,
+            };
+            Ok(Statement::AnnotatedAssignment { target, annotation, value })
+        }
+        Rule::expression_statement => {
+            let expression = parse_expression(next_pair(&mut pair.into_inner(), span, "expression")?)?;
+            Ok(Statement::Expression(expression))
         }
         Rule::return_statement => {
             let mut inner = pair.into_inner();
-            let value_pair = inner.next().unwrap();
-            let value = parse_expression(value_pair)?;
+            inner.next();
+            let value = match inner.next() {
+                Some(value) => Some(parse_expression_tuple(value)?),
+                None => None,
+            };
             Ok(Statement::Return { value })
         }
         Rule::if_statement => {
             let mut inner = pair.into_inner();
             inner.next();
-            let condition = parse_expression(inner.next().unwrap())?;
-            let consequence = parse_block(inner.next().unwrap())?;
-            let alternative = if let Some(else_token) = inner.next() {
-                if else_token.as_rule() == Rule::KEYWORD_ELSE {
-                    Some(parse_block(inner.next().unwrap())?)
-                } else {
-                    None
+            let condition = parse_expression(next_pair(&mut inner, span, "condition")?)?;
+            let consequence = parse_block(next_pair(&mut inner, span, "block")?, errors)?;
+
+            let mut elif_clauses = Vec::new();
+            let mut alternative = None;
+            for clause in inner {
+                match clause.as_rule() {
+                    Rule::elif_clause => {
+                        let clause_span: Span = clause.as_span().into();
+                        let mut clause_inner = clause.into_inner();
+                        clause_inner.next(); // Skip `else`
+                        clause_inner.next(); // Skip `if`
+                        let condition = parse_expression(next_pair(&mut clause_inner, clause_span, "condition")?)?;
+                        let consequence = parse_block(next_pair(&mut clause_inner, clause_span, "block")?, errors)?;
+                        elif_clauses.push(ElifClause { condition, consequence, span: clause_span });
+                    }
+                    Rule::block => alternative = Some(parse_block(clause, errors)?),
+                    _ => {} // `else`
                 }
-            } else {
-                None
-            };
+            }
             Ok(Statement::If {
                 condition,
                 consequence,
+                elif_clauses,
                 alternative,
             })
         }
         Rule::for_statement => {
             let mut inner = pair.into_inner();
-            let iterator = parse_expression(inner.next().unwrap())?;
-            let body = parse_block(inner.next().unwrap())?;
-            Ok(Statement::For { iterator, body })
+            let is_async = parse_async(&mut inner);
+            inner.next();
+            let target_pair = next_pair(&mut inner, span, "loop target")?;
+            let target_span: Span = target_pair.as_span().into();
+            let target = parse_target(target_pair)?;
+            check_unpacking(&target, target_span)?;
+            inner.next(); // Skip `in`
+            let iterable = parse_expression(next_pair(&mut inner, span, "iterable")?)?;
+            let body = parse_block(next_pair(&mut inner, span, "block")?, errors)?;
+            let alternative = match inner.nth(1) { // Skip `else`
+                Some(block) => Some(parse_block(block, errors)?),
+                None => None,
+            };
+            Ok(Statement::For { is_async, target, iterable, body, alternative })
         }
         Rule::while_statement => {
             let mut inner = pair.into_inner();
-            let condition = parse_expression(inner.next().unwrap())?;
-            let body = parse_block(inner.next().unwrap())?;
+            inner.next();
+            let condition = parse_expression(next_pair(&mut inner, span, "condition")?)?;
+            let body = parse_block(next_pair(&mut inner, span, "block")?, errors)?;
             Ok(Statement::While { condition, body })
         }
-        Rule::function_def => {
+        Rule::with_statement => {
+            let mut is_async = false;
+            let mut items = Vec::new();
+            let mut body = None;
+            for part in pair.into_inner() {
+                match part.as_rule() {
+                    Rule::with_item => {
+                        let item_span: Span = part.as_span().into();
+                        let mut inner = part.into_inner();
+                        let context = parse_expression(next_pair(&mut inner, item_span, "context manager")?)?;
+                        let target = match inner.nth(1) { // Skip `as`
+                            Some(target) => Some(parse_target(target)?),
+                            None => None,
+                        };
+                        items.push(WithItem { context, target });
+                    }
+                    Rule::block => body = Some(parse_block(part, errors)?),
+                    Rule::KEYWORD_ASYNC => is_async = true,
+                    _ => {} // `with`
+                }
+            }
+            let body = body.ok_or_else(|| BythonParseError::new("expected block", span))?;
+            Ok(Statement::With { is_async, items, body })
+        }
+        Rule::try_statement => {
             let mut inner = pair.into_inner();
+            inner.next();
+            let body = parse_block(next_pair(&mut inner, span, "block")?, errors)?;
 
-            inner.next(); // Skip keyword
+            let mut handlers = Vec::new();
+            let mut is_group = false;
+            let mut alternative = None;
+            let mut finally = None;
+            for clause in inner {
+                match clause.as_rule() {
+                    Rule::except_clause => {
+                        let clause_span: Span = clause.as_span().into();
+                        let (handler, star) = parse_except_clause(clause, errors)?;
+                        if handlers.is_empty() {
+                            is_group = star;
+                        } else if star != is_group {
+                            return Err(BythonParseError::new(
+                                "cannot have both `except` and `except*` on the same `try`",
+                                clause_span,
+                            ));
+                        }
+                        handlers.push(handler);
+                    }
+                    Rule::block => alternative = Some(parse_block(clause, errors)?),
+                    Rule::finally_clause => {
+                        let clause_span: Span = clause.as_span().into();
+                        let mut clause_inner = clause.into_inner();
+                        clause_inner.next(); // Skip `finally`
+                        let block = next_pair(&mut clause_inner, clause_span, "block")?;
+                        finally = Some(parse_block(block, errors)?);
+                    }
+                    _ => {} // `else`
+                }
+            }
+            Ok(Statement::Try { body, handlers, is_group, alternative, finally })
+        }
+        Rule::match_statement => {
+            let mut inner = pair.into_inner();
+            inner.next();
+            let subject = parse_expression(next_pair(&mut inner, span, "subject")?)?;
+            let cases = inner.map(|case| parse_case_clause(case, errors)).collect::<Result<Vec<_>, _>>()?;
+            Ok(Statement::Match { subject, cases })
+        }
+        Rule::import_statement => {
+            let names = pair.into_inner()
+                .skip(1) // Skip `import`
+                .map(parse_import_alias)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Statement::Import { names })
+        }
+        Rule::from_import_statement => {
+            let mut inner = pair.into_inner();
+            inner.next();
+            let module_path = next_pair(&mut inner, span, "module name")?.as_str();
+            let module = module_path.trim_start_matches('.');
+            let level = module_path.len() - module.len();
+            let module = (!module.is_empty()).then(|| module.to_string());
+            inner.next(); // Skip `import`
+            let names_pair = next_pair(&mut inner, span, "imported names")?;
+            let names = match names_pair.as_rule() {
+                Rule::import_star => None,
+                _ => Some(names_pair.into_inner()
+                    .map(parse_import_alias)
+                    .collect::<Result<Vec<_>, _>>()?),
+            };
+            Ok(Statement::FromImport { module, level, names })
+        }
+        Rule::raise_statement => {
+            let mut inner = pair.into_inner();
+            inner.next();
+            let exception = match inner.next() {
+                Some(exception) => Some(parse_expression(exception)?),
+                None => None,
+            };
+            let cause = match inner.nth(1) { // Skip `from`
+                Some(cause) => Some(parse_expression(cause)?),
+                None => None,
+            };
+            Ok(Statement::Raise { exception, cause })
+        }
+        Rule::function_def => {
+            let mut inner = pair.into_inner();
+            let decorators = parse_decorators(&mut inner)?;
+            let is_async = parse_async(&mut inner);
+            inner.next(); // Skip `fn`
 
-            let name_pair = inner.next().unwrap();
+            let name_pair = next_pair(&mut inner, span, "function name")?;
             let name = match name_pair.as_rule() {
                 Rule::ident | Rule::dunder_ident => name_pair.as_str().to_string(),
-                _ => return Err(BythonParseError {
-                    message: format!("Expected function name, got {:?}", name_pair.as_rule())
-                })
+                _ => return Err(BythonParseError::at(
+                    format!("expected function name, got {:?}", name_pair.as_rule()),
+                    &name_pair,
+                ))
             };
 
-            let mut args = Vec::new();
-            let arg_list_pair = inner.next().unwrap();
-            
-            match arg_list_pair.as_rule() {
-                Rule::arg_list => {
-                    for arg_pair in arg_list_pair.into_inner() {
-                        if arg_pair.as_rule() == Rule::ident {
-                            args.push(arg_pair.as_str().to_string());
-                        }
-                    }
-                },
-                Rule::param_list => {
-                    for arg_pair in arg_list_pair.into_inner() {
-                        if arg_pair.as_rule() == Rule::ident {
-                            args.push(arg_pair.as_str().to_string());
-                        }
-                    }
-                },
-                _ => return Err(BythonParseError {
-                    message: format!("Expected argument list, got {:?}", arg_list_pair.as_rule())
-                })
-            }
+            let parameters = parse_parameters(next_pair(&mut inner, span, "parameter list")?)?;
 
-            let body = parse_block(inner.next().unwrap())?;
+            let mut body_pair = next_pair(&mut inner, span, "function body")?;
+            let mut returns = None;
+            if body_pair.as_rule() == Rule::return_annotation {
+                let annotation_span: Span = body_pair.as_span().into();
+                returns = Some(parse_expression(next_pair(&mut body_pair.into_inner(), annotation_span, "return type")?)?);
+                body_pair = next_pair(&mut inner, span, "function body")?;
+            }
+            let body = parse_block(body_pair, errors)?;
 
-            Ok(Statement::FunctionDef { name, args, body })
+            Ok(Statement::FunctionDef { decorators, is_async, name, parameters, returns, body })
         }
-        Rule::function_call => {
+        Rule::class_def => {
             let mut inner = pair.into_inner();
-            let mut name = String::new();
-
-            let name_part = inner.next().unwrap();
-            match name_part.as_rule() {
-                Rule::ident => name = name_part.as_str().to_string(),
-                Rule::member_access => {
-                    name = name_part.as_str().to_string();
-                },
-                _ => return Err(BythonParseError {
-                    message: format!("Unexpected rule for function name: {:?}", name_part.as_rule()),
+            let decorators = parse_decorators(&mut inner)?;
+            inner.next(); // Skip `class`
+            let name = next_pair(&mut inner, span, "class name")?.as_str().to_string();
+            let mut body_pair = next_pair(&mut inner, span, "class body")?;
+            let mut arguments = Vec::new();
+            if body_pair.as_rule() == Rule::class_arguments {
+                arguments = parse_arguments(body_pair)?;
+                body_pair = next_pair(&mut inner, span, "class body")?;
+            }
+            let body = parse_block(body_pair, errors)?;
+            Ok(Statement::ClassDef { decorators, name, arguments, body })
+        }
+        Rule::break_statement => Ok(Statement::Break),
+        Rule::continue_statement => Ok(Statement::Continue),
+        Rule::pass_statement => Ok(Statement::Pass),
+        Rule::del_statement => {
+            let targets = pair.into_inner()
+                .skip(1)
+                .map(|target| {
+                    let target_span: Span = target.as_span().into();
+                    delete_target(parse_primary(target)?, target_span)
                 })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Statement::Delete { targets })
+        }
+        Rule::assert_statement => {
+            let mut inner = pair.into_inner();
+            inner.next();
+            let test = parse_expression(next_pair(&mut inner, span, "condition")?)?;
+            let message = match inner.next() {
+                Some(message) => Some(parse_expression(message)?),
+                None => None,
+            };
+            Ok(Statement::Assert { test, message })
+        }
+        Rule::global_statement | Rule::nonlocal_statement => {
+            let is_global = pair.as_rule() == Rule::global_statement;
+            let names = pair.into_inner()
+                .skip(1)
+                .map(|name| name.as_str().to_string())
+                .collect();
+            if is_global {
+                Ok(Statement::Global { names })
+            } else {
+                Ok(Statement::Nonlocal { names })
             }
+        }
+        Rule::statement => {
+            let inner = next_pair(&mut pair.into_inner(), span, "statement")?;
+            parse_statement(inner, errors)
+        }
+        _ => Err(BythonParseError::at(
+            format!("unexpected rule for statement: {:?}", pair.as_rule()),
+            &pair,
+        )),
+    }
+}
 
-            let mut arguments = Vec::new();
-            for arg_list in inner {
-                if arg_list.as_rule() == Rule::param_list {
-                    for arg in arg_list.into_inner() {
-                        arguments.push(parse_expression(arg)?);
-                    }
-                }
-            }
+/// Parses `name` or `name as alias` in an import statement.
+fn parse_import_alias(pair: Pair<Rule>) -> Result<ImportAlias, BythonParseError> {
+    let span: Span = pair.as_span().into();
+    let mut inner = pair.into_inner();
+    let name = next_pair(&mut inner, span, "name")?.as_str().to_string();
+    let alias = inner.nth(1).map(|alias| alias.as_str().to_string()); // Skip `as`
+    Ok(ImportAlias { name, alias })
+}
 
-            Ok(Statement::FunctionCall { name, arguments })
-        },
-        Rule::function_call_stmt => {
-            // Handle function call statements
-            let inner = pair.into_inner().next().unwrap();
-            match inner.as_rule() {
-                Rule::function_call => parse_statement(inner),
-                Rule::class_instantiation => {
-                    // Convert class instantiation to function call statement
-                    let mut class_inner = inner.into_inner();
-                    let class_name = class_inner.next().unwrap().as_str().to_string();
-                    let mut arguments = Vec::new();
-
-                    if let Some(arg_pair) = class_inner.next() {
-                        if arg_pair.as_rule() == Rule::term {
-                            arguments.push(parse_expression(arg_pair)?);
-                        }
-                    }
+/// Parses an `except` clause, also returning whether it was written `except*`.
+fn parse_except_clause(pair: Pair<Rule>, errors: &mut Vec<BythonParseError>) -> Result<(ExceptHandler, bool), BythonParseError> {
+    let span: Span = pair.as_span().into();
+    let mut star = false;
+    let mut types = Vec::new();
+    let mut name = None;
+    let mut body = None;
+    for part in pair.into_inner() {
+        match part.as_rule() {
+            Rule::except_star => star = true,
+            Rule::expression => types.push(parse_expression(part)?),
+            Rule::ident => name = Some(part.as_str().to_string()),
+            Rule::block => body = Some(parse_block(part, errors)?),
+            _ => {} // `except`, `as`
+        }
+    }
+    if star && types.is_empty() {
+        return Err(BythonParseError::new("`except*` needs an exception type", span));
+    }
+    let body = body.ok_or_else(|| BythonParseError::new("expected block", span))?;
+    Ok((ExceptHandler { types, name, body, span }, star))
+}
+
+fn parse_case_clause(pair: Pair<Rule>, errors: &mut Vec<BythonParseError>) -> Result<MatchCase, BythonParseError> {
+    let span: Span = pair.as_span().into();
+    let mut inner = pair.into_inner();
+    inner.next();
+    let pattern = parse_pattern(next_pair(&mut inner, span, "pattern")?)?;
+    let mut body_pair = next_pair(&mut inner, span, "block")?;
+    let mut guard = None;
+    if body_pair.as_rule() == Rule::guard {
+        let guard_span: Span = body_pair.as_span().into();
+        let mut guard_inner = body_pair.into_inner();
+        guard_inner.next(); // Skip `if`
+        guard = Some(parse_expression(next_pair(&mut guard_inner, guard_span, "condition")?)?);
+        body_pair = next_pair(&mut inner, span, "block")?;
+    }
+    let body = parse_block(body_pair, errors)?;
+    Ok(MatchCase { pattern, guard, body, span })
+}
 
-                    Ok(Statement::FunctionCall { name: class_name, arguments })
-                },
-                _ => {
-                    // Handle member function calls (e.g., obj.method())
-                    let expr = parse_expression(inner.clone())?;
-                    match expr {
-                        Expression::FunctionCall { name, args } => {
-                            Ok(Statement::FunctionCall { name, arguments: args })
-                        },
-                        _ => Err(BythonParseError {
-                            message: format!("Expected function call in statement, got {:?}", inner.as_rule())
-                        })
+fn parse_pattern(pair: Pair<Rule>) -> Result<Pattern, BythonParseError> {
+    let span: Span = pair.as_span().into();
+    match pair.as_rule() {
+        Rule::pattern => {
+            let mut inner = pair.into_inner();
+            let pattern = parse_pattern(next_pair(&mut inner, span, "pattern")?)?;
+            match inner.nth(1) { // Skip `as`
+                Some(name) => Ok(Pattern::As { pattern: Box::new(pattern), name: name.as_str().to_string() }),
+                None => Ok(pattern),
+            }
+        }
+        Rule::or_pattern => {
+            let mut alternatives = pair.into_inner().map(parse_pattern).collect::<Result<Vec<_>, _>>()?;
+            if alternatives.len() == 1 {
+                Ok(alternatives.remove(0))
+            } else {
+                Ok(Pattern::Or(alternatives))
+            }
+        }
+        Rule::literal_pattern => {
+            let mut inner = pair.into_inner().peekable();
+            let negative = inner.next_if(|part| part.as_rule() == Rule::negate).is_some();
+            let first = inner.next().ok_or_else(|| BythonParseError::new("expected literal", span))?;
+            if first.as_rule() == Rule::pattern_constant {
+                return Ok(Pattern::Literal(Expression::Identifier(first.as_str().to_string())));
+            }
+            let mut literal = parse_term(first)?;
+            if negative {
+                literal = Expression::UnaryOp { operator: UnaryOperator::Neg, operand: Box::new(literal) };
+            }
+            // `1 + 2j`: the only arithmetic Python allows in a pattern.
+            if let (Some(operator), Some(imaginary)) = (inner.next(), inner.next()) {
+                literal = Expression::BinaryOp {
+                    left: Box::new(literal),
+                    operator: parse_operator(operator)?,
+                    right: Box::new(parse_term(imaginary)?),
+                };
+            }
+            Ok(Pattern::Literal(literal))
+        }
+        Rule::value_pattern => Ok(Pattern::Value(dotted_expression(pair.as_str()))),
+        Rule::wildcard_pattern => Ok(Pattern::Wildcard),
+        Rule::capture_pattern => Ok(Pattern::Capture(pair.as_str().to_string())),
+        Rule::class_pattern => {
+            let mut inner = pair.into_inner();
+            let class = dotted_expression(next_pair(&mut inner, span, "class name")?.as_str());
+            let mut positional = Vec::new();
+            let mut keywords: Vec<(String, Pattern)> = Vec::new();
+            for argument in inner {
+                let argument_span: Span = argument.as_span().into();
+                if argument.as_rule() == Rule::keyword_pattern {
+                    let mut argument_inner = argument.into_inner();
+                    let name = next_pair(&mut argument_inner, argument_span, "attribute name")?.as_str().to_string();
+                    if keywords.iter().any(|(existing, _)| *existing == name) {
+                        return Err(BythonParseError::new(
+                            format!("attribute name repeated in class pattern: `{}`", name),
+                            argument_span,
+                        ));
+                    }
+                    let pattern = parse_pattern(next_pair(&mut argument_inner, argument_span, "pattern")?)?;
+                    keywords.push((name, pattern));
+                } else if keywords.is_empty() {
+                    positional.push(parse_pattern(argument)?);
+                } else {
+                    // After keyword patterns a bare name is shorthand for
+                    // `name=name`: `Point(x=0, y)` binds the `y` attribute.
+                    let Pattern::Capture(name) = parse_pattern(argument)? else {
+                        return Err(BythonParseError::new("positional patterns follow keyword patterns", argument_span));
+                    };
+                    if keywords.iter().any(|(existing, _)| *existing == name) {
+                        return Err(BythonParseError::new(
+                            format!("attribute name repeated in class pattern: `{}`", name),
+                            argument_span,
+                        ));
                     }
+                    keywords.push((name.clone(), Pattern::Capture(name)));
                 }
             }
-        },
-        Rule::class_def => {
-            let mut inner = pair.into_inner();
-            inner.next();
-            let name = inner.next().unwrap().as_str().to_string();
-            let body = parse_block(inner.next().unwrap())?;
-            Ok(Statement::ClassDef { name, body })
+            Ok(Pattern::Class { class, positional, keywords })
         }
-        Rule::statement => {
-            let inner = pair.into_inner().next().ok_or_else(|| BythonParseError {
-                message: "Empty statement".to_string(),
-            })?;
-            parse_statement(inner)
+        Rule::sequence_pattern => {
+            // `(p)` just groups `p`; a sequence in parentheses needs a comma.
+            if pair.as_str().starts_with('(') && pair.clone().into_inner().count() == 1 {
+                let item = next_pair(&mut pair.into_inner(), span, "pattern")?;
+                if item.as_rule() == Rule::star_pattern {
+                    return Err(BythonParseError::at("starred name outside of a sequence pattern", &item));
+                }
+                return parse_pattern(item);
+            }
+            let mut items = Vec::new();
+            for item in pair.into_inner().filter(|item| item.as_rule() != Rule::tuple_comma) {
+                if item.as_rule() != Rule::star_pattern {
+                    items.push(parse_pattern(item)?);
+                    continue;
+                }
+                if items.iter().any(|item| matches!(item, Pattern::Star(_))) {
+                    return Err(BythonParseError::at("multiple starred names in sequence pattern", &item));
+                }
+                let name = next_pair(&mut item.into_inner(), span, "name")?;
+                items.push(Pattern::Star(match name.as_rule() {
+                    Rule::wildcard_pattern => None,
+                    _ => Some(name.as_str().to_string()),
+                }));
+            }
+            Ok(Pattern::Sequence(items))
+        }
+        Rule::mapping_pattern => {
+            let mut entries = Vec::new();
+            let mut rest = None;
+            for item in pair.into_inner() {
+                let item_span: Span = item.as_span().into();
+                let mut item_inner = item.into_inner();
+                let first = next_pair(&mut item_inner, item_span, "key")?;
+                if first.as_rule() == Rule::ident {
+                    rest = Some(first.as_str().to_string());
+                    continue;
+                }
+                let key = match parse_pattern(first)? {
+                    Pattern::Literal(key) | Pattern::Value(key) => key,
+                    _ => return Err(BythonParseError::new("expected a literal or dotted name as mapping key", item_span)),
+                };
+                let value = parse_pattern(next_pair(&mut item_inner, item_span, "pattern")?)?;
+                entries.push((key, value));
+            }
+            Ok(Pattern::Mapping { entries, rest })
         }
-        _ => Err(BythonParseError {
-            message: format!("Unexpected rule for statement: {:?}", pair.as_rule()),
-        }),
+        rule => Err(BythonParseError::at(format!("expected pattern, got {:?}", rule), &pair)),
     }
 }
 
+/// Builds `a.b.c` as nested member accesses.
+fn dotted_expression(name: &str) -> Expression {
+    let mut parts = name.split('.');
+    let first = Expression::Identifier(parts.next().unwrap_or_default().to_string());
+    parts.fold(first, |object, member| Expression::MemberAccess {
+        object: Box::new(object),
+        member: member.to_string(),
+    })
+}
+
 fn parse_expression(pair: Pair<Rule>) -> Result<Expression, BythonParseError> {
     match pair.as_rule() {
-        Rule::member_access => {
-            let mut parts = pair.into_inner();
-            let object = Expression::Identifier(parts.next().unwrap().as_str().to_string());
-            let member = parts.next().unwrap().as_str().to_string();
-
-            Ok(Expression::MemberAccess {
-                object: Box::new(object),
-                member
-            })
-        },
-        Rule::BinOperation => {
-            let mut parts = pair.into_inner();
-            let left = parse_expression(parts.next().unwrap())?;
-            let operator = parts.next().unwrap().as_str().to_string();
-            let right = parse_expression(parts.next().unwrap())?;
-
-            Ok(Expression::BinaryOp {
-                left: Box::new(left),
-                operator,
-                right: Box::new(right),
-            })
-        }
+        // Each operand is paired with whether it is a comparison that further
+        // comparisons extend: `a < b < c` is one chain, while in `(a < b) < c`
+        // the parenthesised comparison is an operand of its own.
         Rule::expression => {
             let pairs = pair.into_inner();
-            Ok(PRATT_PARSER
-                .map_primary(|pair| parse_term(pair))
-                .map_infix(|lhs: Result<Expression, BythonParseError>, op: Pair<Rule>, rhs: Result<Expression, BythonParseError>| {
-                    match (lhs, rhs) {
-                        (Ok(left), Ok(right)) => Ok(Expression::BinaryOp {
-                            left: Box::new(left),
-                            operator: op.as_str().to_string(),
-                            right: Box::new(right),
-                        }),
-                        (Err(e), _) => Err(e),
-                        (_, Err(e)) => Err(e),
+            PRATT_PARSER
+                .map_primary(|primary| Ok((parse_term(primary)?, false)))
+                .map_prefix(|op: Pair<Rule>, operand: Result<(Expression, bool), BythonParseError>| {
+                    Ok((Expression::UnaryOp {
+                        operator: parse_unary_operator(op)?,
+                        operand: Box::new(operand?.0),
+                    }, false))
+                })
+                .map_infix(|lhs: Result<(Expression, bool), BythonParseError>, op: Pair<Rule>, rhs: Result<(Expression, bool), BythonParseError>| {
+                    let (left, is_chain) = lhs?;
+                    let (right, _) = rhs?;
+                    if matches!(op.as_rule(), Rule::conditional_if | Rule::conditional_question) {
+                        let is_if = op.as_rule() == Rule::conditional_if;
+                        let span: Span = op.as_span().into();
+                        let middle = op.into_inner()
+                            .find(|part| part.as_rule() == Rule::expression)
+                            .ok_or_else(|| BythonParseError::new("expected expression", span))?;
+                        let middle = Box::new(parse_expression(middle)?);
+                        let (test, body) = if is_if { (middle, Box::new(left)) } else { (Box::new(left), middle) };
+                        return Ok((Expression::Conditional { test, body, orelse: Box::new(right) }, false));
+                    }
+                    let operator = parse_operator(op)?;
+                    if !is_comparison(operator) {
+                        return Ok((Expression::BinaryOp { left: Box::new(left), operator, right: Box::new(right) }, false));
+                    }
+                    match left {
+                        Expression::Compare { left, mut comparisons } if is_chain => {
+                            comparisons.push((operator, right));
+                            Ok((Expression::Compare { left, comparisons }, true))
+                        }
+                        left => Ok((Expression::Compare { left: Box::new(left), comparisons: vec![(operator, right)] }, true)),
                     }
                 })
-                .parse(pairs)?)
+                .parse(pairs)
+                .map(|(expression, _)| expression)
         },
-        Rule::class_instantiation => {
+        _ => parse_term(pair),
+    }
+}
+
+fn parse_term(pair: Pair<Rule>) -> Result<Expression, BythonParseError> {
+    let span: Span = pair.as_span().into();
+    match pair.as_rule() {
+        Rule::primary => parse_primary(pair),
+        Rule::ident => Ok(Expression::Identifier(pair.as_str().to_string())),
+        Rule::integer => Ok(Expression::Integer(pair.as_str().to_string())),
+        Rule::float => Ok(Expression::Float(pair.as_str().to_string())),
+        Rule::imaginary => Ok(Expression::Imaginary(pair.as_str().to_string())),
+        Rule::string => Ok(Expression::String(pair.as_str().to_string())),
+        Rule::fstring => {
             let mut inner = pair.into_inner();
-            let class_name = inner.next().unwrap().as_str().to_string();
-            let mut arguments = Vec::new();
+            let prefix = next_pair(&mut inner, span, "f-string prefix")?.as_str().to_string();
+            let quote = next_pair(&mut inner, span, "quote")?.as_str().to_string();
+            let parts = inner.map(parse_fstring_part).collect::<Result<Vec<_>, _>>()?;
+            Ok(Expression::FormattedString { prefix, quote, parts })
+        }
+        Rule::parenthesised => {
+            let mut inner = pair.into_inner();
+            let Some(first) = inner.next() else {
+                return Ok(Expression::Tuple(Vec::new()));
+            };
+            let first = parse_expression(first)?;
+            if let Some(generators) = parse_comprehensions(&mut inner)? {
+                return Ok(Expression::Generator { element: Box::new(first), generators });
+            }
+            // Anything after the first item starts with the comma that makes a tuple.
+            if inner.next().is_none() {
+                return Ok(first);
+            }
+            let mut items = vec![first];
+            for item in inner {
+                items.push(parse_expression(item)?);
+            }
+            Ok(Expression::Tuple(items))
+        }
+        Rule::bracketed => {
+            let mut inner = pair.into_inner();
+            let Some(first) = inner.next() else {
+                return Ok(Expression::List(Vec::new()));
+            };
+            let first = parse_expression(first)?;
+            if let Some(generators) = parse_comprehensions(&mut inner)? {
+                return Ok(Expression::ListComprehension { element: Box::new(first), generators });
+            }
+            let mut items = vec![first];
+            for item in inner {
+                items.push(parse_expression(item)?);
+            }
+            Ok(Expression::List(items))
+        }
+        Rule::braced => {
+            let mut inner = pair.into_inner();
+            let Some(first) = inner.next() else {
+                return Ok(Expression::Dict(Vec::new()));
+            };
+            let first = parse_expression(first)?;
+            if inner.peek().is_some_and(|pair| pair.as_rule() == Rule::dict_value) {
+                let value = parse_dict_value(next_pair(&mut inner, span, "value")?)?;
+                if let Some(generators) = parse_comprehensions(&mut inner)? {
+                    return Ok(Expression::DictComprehension { key: Box::new(first), value: Box::new(value), generators });
+                }
+                let mut entries = vec![(first, value)];
+                for entry in inner {
+                    entries.push(parse_dict_entry(entry)?);
+                }
+                return Ok(Expression::Dict(entries));
+            }
+            if let Some(generators) = parse_comprehensions(&mut inner)? {
+                return Ok(Expression::SetComprehension { element: Box::new(first), generators });
+            }
+            let mut items = vec![first];
+            for item in inner {
+                items.push(parse_expression(item)?);
+            }
+            Ok(Expression::Set(items))
+        }
+        Rule::await_expression => {
+            let mut inner = pair.into_inner();
+            inner.next();
+            let value = parse_primary(next_pair(&mut inner, span, "expression")?)?;
+            Ok(Expression::Await { value: Box::new(value) })
+        }
+        Rule::named_expression => {
+            let mut inner = pair.into_inner();
+            let name = next_pair(&mut inner, span, "identifier")?.as_str().to_string();
+            let value = parse_expression(next_pair(&mut inner, span, "expression")?)?;
+            Ok(Expression::Walrus { name, value: Box::new(value) })
+        }
+        Rule::yield_expression => {
+            let mut inner = pair.into_inner();
+            inner.next();
+            match inner.next() {
+                None => Ok(Expression::Yield { value: None }),
+                Some(from) if from.as_rule() == Rule::KEYWORD_FROM => {
+                    let value = parse_expression(next_pair(&mut inner, span, "expression")?)?;
+                    Ok(Expression::YieldFrom { value: Box::new(value) })
+                }
+                Some(value) => Ok(Expression::Yield { value: Some(Box::new(parse_expression_tuple(value)?)) }),
+            }
+        }
+        Rule::lambda_expression => {
+            let mut inner = pair.into_inner();
+            inner.next();
+            let mut body = next_pair(&mut inner, span, "lambda body")?;
+            let mut parameters = Vec::new();
+            if body.as_rule() == Rule::lambda_parameters {
+                parameters = parse_parameters(body)?;
+                body = next_pair(&mut inner, span, "lambda body")?;
+            }
+            let body = LambdaBody::Expression(Box::new(parse_expression(body)?));
+            Ok(Expression::Lambda { parameters, body })
+        }
+        Rule::arrow_function => {
+            let mut inner = pair.into_inner();
+            let parameters = parse_parameters(next_pair(&mut inner, span, "parameter list")?)?;
+            let body = next_pair(&mut inner, span, "function body")?;
+            let body = match body.as_rule() {
+                Rule::block => {
+                    // Expressions have nowhere to collect errors, so only the
+                    // first error in the body is reported.
+                    let mut errors = Vec::new();
+                    let block = parse_block(body, &mut errors)?;
+                    if !errors.is_empty() {
+                        return Err(errors.remove(0));
+                    }
+                    LambdaBody::Block(block)
+                }
+                _ => LambdaBody::Expression(Box::new(parse_expression(body)?)),
+            };
+            Ok(Expression::Lambda { parameters, body })
+        }
+        _ => Err(BythonParseError::at(
+            format!("unexpected rule for term: {:?}", pair.as_rule()),
+            &pair,
+        )),
+    }
+}
+
+/// Folds an atom and its postfix operations, left to right, into one expression.
+fn parse_primary(pair: Pair<Rule>) -> Result<Expression, BythonParseError> {
+    let span: Span = pair.as_span().into();
+    let mut inner = pair.into_inner();
+    let mut expression = parse_term(next_pair(&mut inner, span, "expression")?)?;
+    for postfix in inner {
+        let postfix_span: Span = postfix.as_span().into();
+        expression = match postfix.as_rule() {
+            Rule::call => {
+                let arguments = parse_arguments(postfix)?;
+                Expression::Call { function: Box::new(expression), arguments }
+            }
+            Rule::attribute => Expression::MemberAccess {
+                object: Box::new(expression),
+                member: next_pair(&mut postfix.into_inner(), postfix_span, "attribute name")?.as_str().to_string(),
+            },
+            _ => Expression::Subscript {
+                value: Box::new(expression),
+                index: Box::new(parse_subscript(postfix)?),
+            },
+        };
+    }
+    Ok(expression)
+}
+
+/// Parses the decorators at the start of a definition.
+fn parse_decorators(inner: &mut Pairs<Rule>) -> Result<Vec<Spanned<Expression>>, BythonParseError> {
+    let mut decorators = Vec::new();
+    while let Some(pair) = inner.peek().filter(|pair| pair.as_rule() == Rule::decorator) {
+        inner.next();
+        let span: Span = pair.as_span().into();
+        let node = parse_expression(next_pair(&mut pair.into_inner(), span, "decorator")?)?;
+        decorators.push(Spanned { node, span });
+    }
+    Ok(decorators)
+}
+
+/// Consumes an `async` modifier, if there is one.
+fn parse_async(inner: &mut Pairs<Rule>) -> bool {
+    let is_async = inner.peek().is_some_and(|pair| pair.as_rule() == Rule::KEYWORD_ASYNC);
+    if is_async {
+        inner.next();
+    }
+    is_async
+}
+
+fn parse_dict_entry(pair: Pair<Rule>) -> Result<(Expression, Expression), BythonParseError> {
+    let span: Span = pair.as_span().into();
+    let mut parts = pair.into_inner();
+    let key = parse_expression(next_pair(&mut parts, span, "key")?)?;
+    let value = parse_dict_value(next_pair(&mut parts, span, "value")?)?;
+    Ok((key, value))
+}
+
+/// Parses the `: value` half of a dict entry.
+fn parse_dict_value(pair: Pair<Rule>) -> Result<Expression, BythonParseError> {
+    let span: Span = pair.as_span().into();
+    parse_expression(next_pair(&mut pair.into_inner(), span, "value")?)
+}
+
+/// Parses the `for` clauses after the element of a comprehension, or returns
+/// `None` if there are none and the brackets hold a display instead.
+fn parse_comprehensions(inner: &mut Pairs<Rule>) -> Result<Option<Vec<Comprehension>>, BythonParseError> {
+    if inner.peek().is_none_or(|pair| pair.as_rule() != Rule::comprehension_for) {
+        return Ok(None);
+    }
+    inner.map(parse_comprehension).collect::<Result<Vec<_>, _>>().map(Some)
+}
+
+fn parse_comprehension(pair: Pair<Rule>) -> Result<Comprehension, BythonParseError> {
+    let span: Span = pair.as_span().into();
+    let mut inner = pair.into_inner();
+    inner.next();
+    let target = parse_target(next_pair(&mut inner, span, "loop target")?)?;
+    inner.next(); // Skip `in`
+    let iterable = parse_expression(next_pair(&mut inner, span, "iterable")?)?;
+    let mut conditions = Vec::new();
+    for condition in inner {
+        let condition_span: Span = condition.as_span().into();
+        let mut condition_inner = condition.into_inner();
+        condition_inner.next(); // Skip `if`
+        conditions.push(parse_expression(next_pair(&mut condition_inner, condition_span, "condition")?)?);
+    }
+    Ok(Comprehension { target, iterable, conditions })
+}
 
-            if let Some(arg_pair) = inner.next() {
-                if arg_pair.as_rule() == Rule::term {
-                    arguments.push(parse_expression(arg_pair)?);
+/// Parses call arguments, checking that they come in an order Python accepts.
+fn parse_arguments(pair: Pair<Rule>) -> Result<Vec<Argument>, BythonParseError> {
+    let inner = pair.into_inner();
+    // `sum(x for x in xs)`: `for` clauses follow the only argument.
+    if let Some(first) = inner.peek() {
+        let mut rest = inner.clone();
+        rest.next();
+        if let Some(generators) = parse_comprehensions(&mut rest)? {
+            if !matches!(first.as_rule(), Rule::expression | Rule::named_expression) {
+                return Err(BythonParseError::at("generator expression argument must be parenthesised", &first));
+            }
+            let element = Box::new(parse_expression(first)?);
+            return Ok(vec![Argument::Positional(Expression::Generator { element, generators })]);
+        }
+    }
+    let mut arguments: Vec<Argument> = Vec::new();
+    for argument in inner {
+        let span: Span = argument.as_span().into();
+        let has_keyword = arguments.iter().any(|a| matches!(a, Argument::Keyword { .. }));
+        let has_keyword_unpack = arguments.iter().any(|a| matches!(a, Argument::UnpackKeywords(_)));
+        arguments.push(match argument.as_rule() {
+            Rule::keyword_argument => {
+                let mut inner = argument.into_inner();
+                let name = next_pair(&mut inner, span, "argument name")?.as_str().to_string();
+                if arguments.iter().any(|a| matches!(a, Argument::Keyword { name: other, .. } if *other == name)) {
+                    return Err(BythonParseError::new(format!("keyword argument `{}` repeated", name), span));
+                }
+                let value = parse_expression(next_pair(&mut inner, span, "argument value")?)?;
+                Argument::Keyword { name, value }
+            }
+            Rule::starred_argument => {
+                if has_keyword_unpack {
+                    return Err(BythonParseError::new("iterable argument unpacking follows keyword argument unpacking", span));
+                }
+                Argument::Unpack(parse_expression(next_pair(&mut argument.into_inner(), span, "expression")?)?)
+            }
+            Rule::double_starred_argument => {
+                Argument::UnpackKeywords(parse_expression(next_pair(&mut argument.into_inner(), span, "expression")?)?)
+            }
+            _ => {
+                if has_keyword_unpack {
+                    return Err(BythonParseError::new("positional argument follows keyword argument unpacking", span));
+                }
+                if has_keyword {
+                    return Err(BythonParseError::new("positional argument follows keyword argument", span));
                 }
+                Argument::Positional(parse_expression(argument)?)
             }
+        });
+    }
+    Ok(arguments)
+}
 
-            Ok(Expression::ClassInstantiation {
-                class_name,
-                arguments
-            })
-        },
-        Rule::function_call => {
-            let mut inner = pair.into_inner();
-            let name = inner.next().unwrap().as_str().to_string();
-            let mut args = Vec::new();
+/// Parses a function's parameters, working out each one's kind from the `/`
+/// and `*` markers around it.
+fn parse_parameters(pair: Pair<Rule>) -> Result<Vec<Parameter>, BythonParseError> {
+    let mut parameters: Vec<Parameter> = Vec::new();
+    let mut kind = ParameterKind::PositionalOrKeyword;
+    let mut seen_slash = false;
+    let mut seen_default = false;
+    // A bare `*` must be followed by at least one named parameter.
+    let mut bare_star = None;
 
-            for arg_list in inner {
-                if arg_list.as_rule() == Rule::param_list {
-                    for arg in arg_list.into_inner() {
-                        args.push(parse_expression(arg)?);
-                    }
+    for item in pair.into_inner() {
+        let span: Span = item.as_span().into();
+        if let Some(last) = parameters.last().filter(|p| p.kind == ParameterKind::VarKeyword) {
+            return Err(BythonParseError::new(format!("`**{}` must be the last parameter", last.name), span));
+        }
+        match item.as_rule() {
+            Rule::positional_only_marker => {
+                if seen_slash {
+                    return Err(BythonParseError::new("`/` may appear only once", span));
+                }
+                if kind != ParameterKind::PositionalOrKeyword {
+                    return Err(BythonParseError::new("`/` must come before `*`", span));
+                }
+                if parameters.is_empty() {
+                    return Err(BythonParseError::new("at least one parameter must come before `/`", span));
+                }
+                for parameter in &mut parameters {
+                    parameter.kind = ParameterKind::PositionalOnly;
                 }
+                seen_slash = true;
             }
+            Rule::keyword_only_marker | Rule::var_positional_parameter | Rule::lambda_var_positional_parameter
+                if kind == ParameterKind::KeywordOnly =>
+            {
+                return Err(BythonParseError::new("`*` may appear only once", span));
+            }
+            Rule::keyword_only_marker => {
+                kind = ParameterKind::KeywordOnly;
+                bare_star = Some(span);
+            }
+            _ => {
+                let rule = item.as_rule();
+                let mut inner = item.into_inner();
+                let name = next_pair(&mut inner, span, "parameter name")?.as_str().to_string();
+                if parameters.iter().any(|p| p.name == name) {
+                    return Err(BythonParseError::new(format!("duplicate parameter `{}`", name), span));
+                }
+                let mut annotation = None;
+                let mut default = None;
+                for part in inner {
+                    let part_span: Span = part.as_span().into();
+                    let part_rule = part.as_rule();
+                    let value = parse_expression(next_pair(&mut part.into_inner(), part_span, "expression")?)?;
+                    match part_rule {
+                        Rule::annotation => annotation = Some(value),
+                        _ => default = Some(value),
+                    }
+                }
 
-            Ok(Expression::FunctionCall { name, args })
-        },
-        Rule::function_call_stmt => {
-            let inner = pair.into_inner().next().unwrap();
-            match inner.as_rule() {
-                Rule::function_call => parse_expression(inner),
-                Rule::class_instantiation => parse_expression(inner),
-                _ => {
-                    let parts: Vec<&str> = inner.as_str().split('.').collect();
-                    if parts.len() == 2 {
-                        let obj_name = parts[0].to_string();
-                        let method_call = parts[1];
-                        
-                        if let Some(paren_pos) = method_call.find('(') {
-                            let method_name = method_call[..paren_pos].to_string();
-                            Ok(Expression::FunctionCall {
-                                name: format!("{}.{}", obj_name, method_name),
-                                args: vec![]
-                            })
-                        } else {
-                            Err(BythonParseError {
-                                message: "Invalid member function call".to_string()
-                            })
+                let parameter_kind = match rule {
+                    Rule::var_positional_parameter | Rule::lambda_var_positional_parameter => {
+                        kind = ParameterKind::KeywordOnly;
+                        ParameterKind::VarPositional
+                    }
+                    Rule::var_keyword_parameter | Rule::lambda_var_keyword_parameter => ParameterKind::VarKeyword,
+                    _ => {
+                        bare_star = None;
+                        if kind == ParameterKind::PositionalOrKeyword {
+                            if default.is_some() {
+                                seen_default = true;
+                            } else if seen_default {
+                                return Err(BythonParseError::new(
+                                    "parameter without a default follows parameter with a default",
+                                    span,
+                                ));
+                            }
                         }
-                    } else {
-                        parse_expression(inner)
+                        kind
                     }
-                }
+                };
+                parameters.push(Parameter { name, kind: parameter_kind, annotation, default });
             }
-        },
-        _ => parse_term(pair),
+        }
     }
+
+    if let Some(span) = bare_star {
+        return Err(BythonParseError::new("named parameters must follow bare `*`", span));
+    }
+    Ok(parameters)
 }
 
-fn parse_term(pair: Pair<Rule>) -> Result<Expression, BythonParseError> {
+/// Parses comma-separated expressions such as `yield k, v` or `return 1, 2`,
+/// which form a tuple when there is a comma and a single value otherwise.
+fn parse_expression_tuple(pair: Pair<Rule>) -> Result<Expression, BythonParseError> {
+    let (items, is_tuple) = split_tuple_comma(pair);
+    let mut items = items.into_iter()
+        .map(parse_expression)
+        .collect::<Result<Vec<_>, _>>()?;
+    if items.len() == 1 && !is_tuple {
+        Ok(items.remove(0))
+    } else {
+        Ok(Expression::Tuple(items))
+    }
+}
+
+fn parse_fstring_part(pair: Pair<Rule>) -> Result<FStringPart, BythonParseError> {
+    let span: Span = pair.as_span().into();
     match pair.as_rule() {
-        Rule::ident => Ok(Expression::Identifier(pair.as_str().to_string())),
-        Rule::number => Ok(Expression::Number(pair.as_str().parse().unwrap())),
-        Rule::string => {
-            let s = pair.as_str();
-            Ok(Expression::String(s[1..s.len() - 1].to_string()))
-        }
-        Rule::paren_expression => parse_expression(pair.into_inner().next().unwrap()),
-        Rule::function_call => parse_expression(pair),
-        Rule::function_call_term => {
+        Rule::fstring_text | Rule::format_spec_text => Ok(FStringPart::Literal(pair.as_str().to_string())),
+        Rule::replacement_field => {
+            let field = pair.as_span();
             let mut inner = pair.into_inner();
-            let name = inner.next().unwrap().as_str().to_string();
-            let mut args = Vec::new();
-
-            for arg_list in inner {
-                if arg_list.as_rule() == Rule::param_list {
-                    for arg in arg_list.into_inner() {
-                        args.push(parse_expression(arg)?);
+            let expression = parse_expression(next_pair(&mut inner, span, "expression")?)?;
+            let mut debug = None;
+            let mut conversion = None;
+            let mut format_spec = Vec::new();
+            while let Some(part) = inner.next() {
+                match part.as_rule() {
+                    Rule::fstring_debug => {
+                        // Everything up to what follows the `=`, spaces included.
+                        let end = inner.peek().map_or(field.end() - 1, |next| next.as_span().start());
+                        debug = Some(field.as_str()[1..end - field.start()].to_string());
+                    }
+                    Rule::fstring_conversion => conversion = part.as_str().chars().nth(1),
+                    _ => {
+                        format_spec = part.into_inner()
+                            .map(parse_fstring_part)
+                            .collect::<Result<Vec<_>, _>>()?;
                     }
                 }
             }
-
-            Ok(Expression::FunctionCall { name, args })
-        },
-        Rule::function_call_stmt => parse_expression(pair),
-        Rule::member_access => {
-            let mut parts = pair.into_inner();
-            let object = Expression::Identifier(parts.next().unwrap().as_str().to_string());
-            let member = parts.next().unwrap().as_str().to_string();
-
-            Ok(Expression::MemberAccess {
-                object: Box::new(object),
-                member
+            Ok(FStringPart::Replacement {
+                expression: Box::new(expression),
+                debug,
+                conversion,
+                format_spec,
+                span,
             })
+        }
+        _ => Err(BythonParseError::at(
+            format!("unexpected rule in f-string: {:?}", pair.as_rule()),
+            &pair,
+        )),
+    }
+}
+
+fn parse_subscript(pair: Pair<Rule>) -> Result<Expression, BythonParseError> {
+    // `a[i, j]` indexes with the tuple `(i, j)`, as in Python.
+    let (items, is_tuple) = split_tuple_comma(pair);
+    let mut items = items.into_iter()
+        .map(parse_subscript_item)
+        .collect::<Result<Vec<_>, _>>()?;
+    if items.len() == 1 && !is_tuple {
+        Ok(items.remove(0))
+    } else {
+        Ok(Expression::Tuple(items))
+    }
+}
+
+fn parse_subscript_item(pair: Pair<Rule>) -> Result<Expression, BythonParseError> {
+    let span: Span = pair.as_span().into();
+    let mut inner = pair.into_inner();
+    let mut slice = next_pair(&mut inner, span, "expression")?;
+    if slice.as_rule() == Rule::named_expression {
+        return parse_expression(slice);
+    }
+    let mut lower = None;
+    if slice.as_rule() == Rule::expression {
+        let index = parse_expression(slice)?;
+        match inner.next() {
+            Some(next) => slice = next,
+            None => return Ok(index),
+        }
+        lower = Some(Box::new(index));
+    }
+    let (mut upper, mut step) = (None, None);
+    for bound in slice.into_inner() {
+        let span: Span = bound.as_span().into();
+        let slot = match bound.as_rule() {
+            Rule::slice_upper => &mut upper,
+            _ => &mut step,
+        };
+        *slot = Some(Box::new(parse_expression(next_pair(&mut bound.into_inner(), span, "expression")?)?));
+    }
+    Ok(Expression::Slice { lower, upper, step })
+}
+
+/// Splits the items of a comma-separated list from the trailing `tuple_comma`
+/// that makes even a single item a tuple: `for x, in pairs` or `a[i,]`.
+fn split_tuple_comma(pair: Pair<Rule>) -> (Vec<Pair<Rule>>, bool) {
+    let mut items: Vec<_> = pair.into_inner().collect();
+    let is_tuple = items.last().is_some_and(|item| item.as_rule() == Rule::tuple_comma);
+    if is_tuple {
+        items.pop();
+    }
+    (items, is_tuple)
+}
+
+fn parse_target(pair: Pair<Rule>) -> Result<Target, BythonParseError> {
+    let span: Span = pair.as_span().into();
+    match pair.as_rule() {
+        Rule::ident => Ok(Target::Identifier(pair.as_str().to_string())),
+        Rule::primary => match parse_primary(pair)? {
+            Expression::Identifier(name) => Ok(Target::Identifier(name)),
+            Expression::MemberAccess { object, member } => Ok(Target::Attribute { object, member }),
+            Expression::Subscript { value, index } => Ok(Target::Subscript { value, index }),
+            Expression::Call { .. } => Err(BythonParseError::new("cannot assign to a function call", span)),
+            _ => Err(BythonParseError::new("cannot assign to a literal", span)),
         },
-        _ => Err(BythonParseError {
-            message: format!("Unexpected rule for term: {:?}", pair.as_rule()),
-        }),
+        Rule::starred_target | Rule::starred_assignment_target => {
+            let name = next_pair(&mut pair.into_inner(), span, "identifier")?;
+            Ok(Target::Starred(Box::new(parse_target(name)?)))
+        }
+        Rule::target_list | Rule::assignment_targets => {
+            // A trailing comma makes even a single target a tuple: `for x, in pairs`.
+            let (targets, is_tuple) = split_tuple_comma(pair);
+            let mut targets = targets.into_iter()
+                .map(parse_target)
+                .collect::<Result<Vec<_>, _>>()?;
+            if targets.len() == 1 && !is_tuple {
+                Ok(targets.remove(0))
+            } else {
+                Ok(Target::Tuple(targets))
+            }
+        }
+        _ => Err(BythonParseError::at(
+            format!("unexpected rule for target: {:?}", pair.as_rule()),
+            &pair,
+        )),
+    }
+}
+
+/// Converts the operand of `del` to a target. Like Python, `del (a, b)` and
+/// `del [a, b]` delete each of their items.
+fn delete_target(expression: Expression, span: Span) -> Result<Target, BythonParseError> {
+    match expression {
+        Expression::Identifier(name) => Ok(Target::Identifier(name)),
+        Expression::MemberAccess { object, member } => Ok(Target::Attribute { object, member }),
+        Expression::Subscript { value, index } => Ok(Target::Subscript { value, index }),
+        Expression::Tuple(items) | Expression::List(items) => Ok(Target::Tuple(
+            items.into_iter()
+                .map(|item| delete_target(item, span))
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
+        Expression::Call { .. } => Err(BythonParseError::new("cannot delete a function call", span)),
+        Expression::Integer(_)
+        | Expression::Float(_)
+        | Expression::Imaginary(_)
+        | Expression::String(_)
+        | Expression::FormattedString { .. } => Err(BythonParseError::new("cannot delete a literal", span)),
+        _ => Err(BythonParseError::new("cannot delete an expression", span)),
     }
 }
 
+/// Checks the starred targets in an unpacking: `a, *b = xs`.
+fn check_unpacking(target: &Target, span: Span) -> Result<(), BythonParseError> {
+    match target {
+        Target::Starred(_) => Err(BythonParseError::new("starred assignment target must be in a list or tuple", span)),
+        Target::Tuple(items) => {
+            if items.iter().filter(|item| matches!(item, Target::Starred(_))).count() > 1 {
+                return Err(BythonParseError::new("multiple starred expressions in assignment", span));
+            }
+            items.iter()
+                .filter(|item| !matches!(item, Target::Starred(_)))
+                .try_for_each(|item| check_unpacking(item, span))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn parse_augmented_operator(pair: Pair<Rule>) -> Result<Operator, BythonParseError> {
+    Ok(match pair.as_str() {
+        "+=" => Operator::Add,
+        "-=" => Operator::Sub,
+        "*=" => Operator::Mul,
+        "/=" => Operator::Div,
+        "//=" => Operator::FloorDiv,
+        "%=" => Operator::Mod,
+        "**=" => Operator::Pow,
+        "@=" => Operator::MatMul,
+        "<<=" => Operator::LShift,
+        ">>=" => Operator::RShift,
+        "&=" => Operator::BitAnd,
+        "|=" => Operator::BitOr,
+        "^=" => Operator::BitXor,
+        operator => return Err(BythonParseError::at(format!("unknown operator `{}`", operator), &pair)),
+    })
+}
+
+fn is_comparison(operator: Operator) -> bool {
+    matches!(
+        operator,
+        Operator::Eq | Operator::NotEq | Operator::Lt | Operator::Gt | Operator::LtEq | Operator::GtEq
+        | Operator::In | Operator::NotIn | Operator::Is | Operator::IsNot
+    )
+}
+
 fn parse_operator(pair: Pair<Rule>) -> Result<Operator, BythonParseError> {
-    match pair.as_str() {
-        "+" => Ok(Operator::Add),
-        "-" => Ok(Operator::Sub),
-        "*" => Ok(Operator::Mul),
-        "/" => Ok(Operator::Div),
-        "==" => Ok(Operator::Eq),
-        "!=" => Ok(Operator::NotEq),
-        "<" => Ok(Operator::Lt),
-        ">" => Ok(Operator::Gt),
-        "<=" => Ok(Operator::LtEq),
-        ">=" => Ok(Operator::GtEq),
-        "and" => Ok(Operator::And),
-        "or" => Ok(Operator::Or),
-        "not" => Ok(Operator::Not),
-        _ => Err(BythonParseError {
-            message: format!("Unknown operator: {}", pair.as_str())
-        })
-    }
-}
-
-fn parse_block(pair: Pair<Rule>) -> Result<Block, BythonParseError> {
+    match pair.as_rule() {
+        Rule::add => Ok(Operator::Add),
+        Rule::subtract => Ok(Operator::Sub),
+        Rule::multiply => Ok(Operator::Mul),
+        Rule::divide => Ok(Operator::Div),
+        Rule::floor_divide => Ok(Operator::FloorDiv),
+        Rule::modulo => Ok(Operator::Mod),
+        Rule::power => Ok(Operator::Pow),
+        Rule::matmul => Ok(Operator::MatMul),
+        Rule::left_shift => Ok(Operator::LShift),
+        Rule::right_shift => Ok(Operator::RShift),
+        Rule::bit_and => Ok(Operator::BitAnd),
+        Rule::bit_or => Ok(Operator::BitOr),
+        Rule::bit_xor => Ok(Operator::BitXor),
+        Rule::eq => Ok(Operator::Eq),
+        Rule::neq => Ok(Operator::NotEq),
+        Rule::lt => Ok(Operator::Lt),
+        Rule::gt => Ok(Operator::Gt),
+        Rule::lte => Ok(Operator::LtEq),
+        Rule::gte => Ok(Operator::GtEq),
+        Rule::in_op => Ok(Operator::In),
+        Rule::not_in_op => Ok(Operator::NotIn),
+        Rule::is_op => Ok(Operator::Is),
+        Rule::is_not_op => Ok(Operator::IsNot),
+        Rule::and_op => Ok(Operator::And),
+        Rule::or_op => Ok(Operator::Or),
+        _ => Err(BythonParseError::at(format!("unknown operator: {}", pair.as_str()), &pair))
+    }
+}
+
+fn parse_unary_operator(pair: Pair<Rule>) -> Result<UnaryOperator, BythonParseError> {
+    match pair.as_rule() {
+        Rule::not_op => Ok(UnaryOperator::Not),
+        Rule::negate => Ok(UnaryOperator::Neg),
+        Rule::unary_plus => Ok(UnaryOperator::Pos),
+        Rule::invert => Ok(UnaryOperator::Invert),
+        _ => Err(BythonParseError::at(format!("unknown unary operator: {}", pair.as_str()), &pair))
+    }
+}
+
+fn parse_block(pair: Pair<Rule>, errors: &mut Vec<BythonParseError>) -> Result<Block, BythonParseError> {
     if pair.as_rule() != Rule::block {
-        return Err(BythonParseError {
-            message: format!("Expected a block rule, got {:?}", pair.as_rule()),
-        });
+        return Err(BythonParseError::at(
+            format!("expected a block, got {:?}", pair.as_rule()),
+            &pair,
+        ));
     }
-    let mut statements = Vec::new();
-    for inner_pair in pair.into_inner() {
-        if inner_pair.as_rule() == Rule::statement {
-            statements.push(parse_statement(inner_pair)?);
-        }
+    Ok(Block { statements: parse_statements(pair.into_inner(), errors) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::{generate_python_code, PythonVersion};
+
+    /// The diagnostics for a program, each rendered as it would be printed.
+    fn rendered_errors(code: &str) -> Vec<String> {
+        let source = SourceFile::new("test.bython", code);
+        parse_bython_code(&source).diagnostics.iter()
+            .map(|diagnostic| diagnostic.render(&source))
+            .collect()
+    }
+
+    /// The diagnostics for a program on one line each, without source snippets.
+    fn errors(code: &str) -> Vec<String> {
+        let source = SourceFile::new("test.bython", code);
+        parse_bython_code(&source).diagnostics.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn spans_end_at_the_last_token() {
+        assert_eq!(rendered_errors("1 = x;"), [
+            "error: cannot assign to a literal\n --> test.bython:1:1\n  |\n1 | 1 = x;\n  | ^\n",
+        ]);
+        assert_eq!(rendered_errors("*a = xs;"), [
+            "error: starred assignment target must be in a list or tuple\n \
+             --> test.bython:1:1\n  |\n1 | *a = xs;\n  | ^^\n",
+        ]);
+        assert_eq!(rendered_errors("f(x) # call\n = 1;"), [
+            "error: cannot assign to a function call\n --> test.bython:1:1\n  |\n1 | f(x) # call\n  | ^^^^\n",
+        ]);
+    }
+
+    #[test]
+    fn recovers_after_bad_statements() {
+        let source = SourceFile::new("test.bython", "x = 1;\ny = = 2;\nprint(x);\nfn f() {\n    z = ;\n    w = 3;\n}\nv = 4;\n");
+        let parsed = parse_bython_code(&source);
+        let errors: Vec<_> = parsed.diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(errors, [
+            "test.bython:2:3: error: expected `(`, `.`, `[`, operator, `if` or `,`",
+            "test.bython:5:9: error: expected expression, assignment target or `{`",
+        ]);
+        // The bad statements are left out and everything around them is kept.
+        let python = generate_python_code(&parsed.program, &source, PythonVersion::default()).unwrap().python;
+        assert_eq!(python, "x = 1\nprint(x)\ndef f():\n    w = 3\nv = 4\n");
+    }
+
+    #[test]
+    fn misplaced_parameter_markers() {
+        assert_eq!(errors("fn f(a, /, b, /) {}"), ["test.bython:1:15: error: `/` may appear only once"]);
+        assert_eq!(errors("fn f(a, *, b, /) {}"), ["test.bython:1:15: error: `/` must come before `*`"]);
+        assert_eq!(errors("fn f(/, a) {}"), ["test.bython:1:6: error: at least one parameter must come before `/`"]);
+        assert_eq!(errors("fn f(*, a, *) {}"), ["test.bython:1:12: error: `*` may appear only once"]);
+        assert_eq!(errors("fn f(*args, *, a) {}"), ["test.bython:1:13: error: `*` may appear only once"]);
+        assert_eq!(errors("fn f(a, *) {}"), ["test.bython:1:9: error: named parameters must follow bare `*`"]);
+        assert_eq!(errors("fn f(**kwargs, a) {}"), ["test.bython:1:16: error: `**kwargs` must be the last parameter"]);
+        assert_eq!(errors("f = lambda *, a, *b: a;"), ["test.bython:1:18: error: `*` may appear only once"]);
     }
-    Ok(Block { statements })
-}
\ No newline at end of file
+
+    #[test]
+    fn parameter_without_default_after_default() {
+        assert_eq!(
+            errors("fn f(a=1, b) {}"),
+            ["test.bython:1:11: error: parameter without a default follows parameter with a default"],
+        );
+        assert_eq!(
+            errors("fn f(a=1, /, b) {}"),
+            ["test.bython:1:14: error: parameter without a default follows parameter with a default"],
+        );
+        // Keyword-only parameters may go without defaults in any order.
+        assert!(errors("fn f(a=1, *, b, c=2, d) {}").is_empty());
+        assert!(errors("fn f(a=1, *args, b) {}").is_empty());
+    }
+
+    #[test]
+    fn duplicate_parameters() {
+        assert_eq!(errors("fn f(a, b, a) {}"), ["test.bython:1:12: error: duplicate parameter `a`"]);
+        assert_eq!(errors("fn f(a, *a) {}"), ["test.bython:1:9: error: duplicate parameter `a`"]);
+        assert_eq!(errors("fn f(a, /, *, b: int = 1, **a) {}"), ["test.bython:1:27: error: duplicate parameter `a`"]);
+        assert_eq!(errors("f = (x, x) => x;"), ["test.bython:1:9: error: duplicate parameter `x`"]);
+    }
+
+    #[test]
+    fn misplaced_arguments() {
+        assert_eq!(errors("f(x=1, y);"), ["test.bython:1:8: error: positional argument follows keyword argument"]);
+        assert_eq!(
+            errors("f(**kw, y);"),
+            ["test.bython:1:9: error: positional argument follows keyword argument unpacking"],
+        );
+        assert_eq!(
+            errors("f(**kw, *xs);"),
+            ["test.bython:1:9: error: iterable argument unpacking follows keyword argument unpacking"],
+        );
+        assert_eq!(errors("f(x=1, x=2);"), ["test.bython:1:8: error: keyword argument `x` repeated"]);
+        // Unpacking an iterable after a keyword argument is allowed, as in Python.
+        assert!(errors("f(x=1, *xs, y=2, **kw);").is_empty());
+    }
+
+    #[test]
+    fn delete_targets() {
+        assert!(errors("del a, (b), [c, d], (e, f.g), h[0], (i[1:2]);").is_empty());
+        assert_eq!(errors("del f();"), ["test.bython:1:5: error: cannot delete a function call"]);
+        assert_eq!(errors("del [a, \"s\"];"), ["test.bython:1:5: error: cannot delete a literal"]);
+        assert_eq!(errors("del (a + b);"), ["test.bython:1:5: error: cannot delete an expression"]);
+        assert_eq!(errors("del a, (b, f());"), ["test.bython:1:8: error: cannot delete a function call"]);
+    }
+
+    #[test]
+    fn walrus_needs_parentheses_outside_conditions_and_items() {
+        assert_eq!(errors("x := 5;"), ["test.bython:1:3: error: expected `(`, `.`, `[`, operator, `if` or `,`"]);
+        assert_eq!(
+            errors("y = x := 5;"),
+            ["test.bython:1:7: error: expected `(`, `.`, `[`, operator, `if`, `,`, `;` or `=`"],
+        );
+        assert_eq!(
+            errors("f(b=c := 2);"),
+            ["test.bython:1:7: error: expected `(`, `.`, `[`, operator, `if`, `for`, `)` or `,`"],
+        );
+        assert_eq!(
+            errors("d = {a := 1: 2};")[0],
+            "test.bython:1:12: error: expected `(`, `.`, `[`, operator, `if`, `for`, `,` or `}`",
+        );
+        assert!(errors("x = (y := 5);\nmatch m := f() { case 1 if n := g() => {} }").is_empty());
+    }
+}
@@ -0,0 +1,684 @@
+use crate::ast::{
+    Argument, Block, Comprehension, Expression, FStringPart, LambdaBody, Parameter, Pattern, Program,
+    Span, Spanned, Statement, Target,
+};
+use crate::diagnostic::{Diagnostic, SourceFile};
+
+/// Checks rules that the grammar cannot express, such as where `return`,
+/// `yield`, `await` and `break` may appear. Python would only report these when the
+/// generated code is compiled, pointing at the generated file instead of the
+/// Bython source.
+pub fn check_program(program: &Program, source: &SourceFile) -> Vec<Diagnostic> {
+    let mut checker = Checker { source, diagnostics: Vec::new(), scopes: Vec::new(), in_loop: false };
+    for statement in &program.statements {
+        checker.statement(statement);
+    }
+    checker.diagnostics
+}
+
+enum Scope {
+    Function(FunctionScope),
+    Class,
+    /// Everything in a comprehension except its first iterable.
+    Comprehension,
+}
+
+#[derive(Default)]
+struct FunctionScope {
+    is_async: bool,
+    is_generator: bool,
+    /// `return value` statements, reported once we know whether the function
+    /// is a generator.
+    value_returns: Vec<Span>,
+}
+
+struct Checker<'a> {
+    source: &'a SourceFile,
+    diagnostics: Vec<Diagnostic>,
+    scopes: Vec<Scope>,
+    /// Whether `break` and `continue` are allowed here. Loops do not reach
+    /// into the functions and classes defined in them.
+    in_loop: bool,
+}
+
+impl Checker<'_> {
+    fn error(&mut self, span: Span, message: &str) {
+        self.diagnostics.push(Diagnostic::new(self.source, span, message));
+    }
+
+    fn warning(&mut self, span: Span, message: &str) {
+        self.diagnostics.push(Diagnostic::warning(self.source, span, message));
+    }
+
+    fn block(&mut self, block: &Block) {
+        for statement in &block.statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Spanned<Statement>) {
+        let span = statement.span;
+        match &statement.node {
+            Statement::Assignment { targets, value } => {
+                for target in targets {
+                    self.target(target, span);
+                }
+                self.expression(value, span);
+            }
+            Statement::AugmentedAssignment { target, value, .. } => {
+                self.target(target, span);
+                self.expression(value, span);
+            }
+            Statement::AnnotatedAssignment { target, annotation, value } => {
+                self.target(target, span);
+                self.expression(annotation, span);
+                if let Some(value) = value {
+                    self.expression(value, span);
+                }
+            }
+            Statement::Return { value } => {
+                match self.scopes.last_mut() {
+                    Some(Scope::Function(function)) => {
+                        if value.is_some() {
+                            function.value_returns.push(span);
+                        }
+                    }
+                    _ => self.error(span, "`return` outside function"),
+                }
+                if let Some(value) = value {
+                    self.expression(value, span);
+                }
+            }
+            Statement::If { condition, consequence, elif_clauses, alternative } => {
+                self.expression(condition, span);
+                self.block(consequence);
+                for clause in elif_clauses {
+                    self.expression(&clause.condition, clause.span);
+                    self.block(&clause.consequence);
+                }
+                if let Some(alternative) = alternative {
+                    self.block(alternative);
+                }
+            }
+            Statement::For { is_async, target, iterable, body, alternative } => {
+                if *is_async {
+                    self.require_async(span, "`async for`");
+                }
+                self.target(target, span);
+                self.expression(iterable, span);
+                self.loop_body(body);
+                if let Some(alternative) = alternative {
+                    self.block(alternative);
+                }
+            }
+            Statement::While { condition, body } => {
+                self.expression(condition, span);
+                self.loop_body(body);
+            }
+            Statement::FunctionDef { decorators, is_async, parameters, returns, body, .. } => {
+                for decorator in decorators {
+                    self.expression(&decorator.node, decorator.span);
+                }
+                self.parameters(parameters, span);
+                if let Some(returns) = returns {
+                    self.expression(returns, span);
+                }
+                self.function(*is_async, |checker| checker.block(body));
+            }
+            Statement::ClassDef { decorators, arguments, body, .. } => {
+                for decorator in decorators {
+                    self.expression(&decorator.node, decorator.span);
+                }
+                self.arguments(arguments, span);
+                let in_loop = std::mem::replace(&mut self.in_loop, false);
+                self.scopes.push(Scope::Class);
+                self.block(body);
+                self.scopes.pop();
+                self.in_loop = in_loop;
+            }
+            Statement::With { is_async, items, body } => {
+                if *is_async {
+                    self.require_async(span, "`async with`");
+                }
+                for item in items {
+                    self.expression(&item.context, span);
+                    if let Some(target) = &item.target {
+                        self.target(target, span);
+                    }
+                }
+                self.block(body);
+            }
+            Statement::Match { subject, cases } => {
+                self.expression(subject, span);
+                for (i, case) in cases.iter().enumerate() {
+                    let mut names = Vec::new();
+                    self.pattern(&case.pattern, case.span, &mut names);
+                    for (j, name) in names.iter().enumerate() {
+                        if names[..j].contains(name) {
+                            self.error(case.span, &format!("multiple assignments to name `{}` in pattern", name));
+                        }
+                    }
+                    if i + 1 < cases.len()
+                        && case.guard.is_none()
+                        && let Some(irrefutable) = irrefutable(&case.pattern)
+                    {
+                        self.error(case.span, &format!("{} makes remaining patterns unreachable", irrefutable));
+                    }
+                    if let Some(guard) = &case.guard {
+                        self.expression(guard, case.span);
+                    }
+                    self.block(&case.body);
+                }
+            }
+            Statement::Try { body, handlers, alternative, finally, .. } => {
+                self.block(body);
+                for handler in handlers {
+                    for exception_type in &handler.types {
+                        self.expression(exception_type, handler.span);
+                    }
+                    self.block(&handler.body);
+                }
+                for block in alternative.iter().chain(finally) {
+                    self.block(block);
+                }
+            }
+            Statement::Raise { exception, cause } => {
+                for expression in exception.iter().chain(cause) {
+                    self.expression(expression, span);
+                }
+            }
+            Statement::Expression(expression) => self.expression(expression, span),
+            Statement::Break if !self.in_loop => self.error(span, "`break` outside loop"),
+            Statement::Continue if !self.in_loop => self.error(span, "`continue` outside loop"),
+            Statement::Delete { targets } => {
+                for target in targets {
+                    self.target(target, span);
+                }
+            }
+            Statement::Assert { test, message } => {
+                self.expression(test, span);
+                if let Some(message) = message {
+                    self.expression(message, span);
+                }
+            }
+            // `nonlocal` names a variable of an enclosing function, so there
+            // has to be one outside the current scope.
+            Statement::Nonlocal { .. }
+                if !self.scopes.iter().rev().skip(1).any(|scope| matches!(scope, Scope::Function(_))) =>
+            {
+                self.error(span, "`nonlocal` outside nested function");
+            }
+            Statement::Import { .. } | Statement::FromImport { .. } | Statement::Break | Statement::Continue
+            | Statement::Pass | Statement::Global { .. } | Statement::Nonlocal { .. } => {}
+        }
+    }
+
+    fn loop_body(&mut self, body: &Block) {
+        let in_loop = std::mem::replace(&mut self.in_loop, true);
+        self.block(body);
+        self.in_loop = in_loop;
+    }
+
+    /// Checks a function body in its own scope.
+    fn function(&mut self, is_async: bool, body: impl FnOnce(&mut Self)) {
+        let in_loop = std::mem::replace(&mut self.in_loop, false);
+        self.scopes.push(Scope::Function(FunctionScope { is_async, ..FunctionScope::default() }));
+        body(self);
+        self.in_loop = in_loop;
+        if let Some(Scope::Function(function)) = self.scopes.pop()
+            && function.is_generator
+        {
+            for span in function.value_returns {
+                if function.is_async {
+                    self.error(span, "`return` with a value inside an async generator");
+                    continue;
+                }
+                self.warning(
+                    span,
+                    "`return` with a value inside a generator: `for` loops discard the value, \
+                     only `yield from` and `StopIteration.value` see it",
+                );
+            }
+        }
+    }
+
+    fn parameters(&mut self, parameters: &[Parameter], span: Span) {
+        for parameter in parameters {
+            for expression in parameter.annotation.iter().chain(&parameter.default) {
+                self.expression(expression, span);
+            }
+        }
+    }
+
+    fn arguments(&mut self, arguments: &[Argument], span: Span) {
+        for argument in arguments {
+            match argument {
+                Argument::Positional(value)
+                | Argument::Keyword { value, .. }
+                | Argument::Unpack(value)
+                | Argument::UnpackKeywords(value) => self.expression(value, span),
+            }
+        }
+    }
+
+    fn target(&mut self, target: &Target, span: Span) {
+        match target {
+            Target::Identifier(_) => {}
+            Target::Attribute { object, .. } => self.expression(object, span),
+            Target::Subscript { value, index } => {
+                self.expression(value, span);
+                self.expression(index, span);
+            }
+            Target::Starred(inner) => self.target(inner, span),
+            Target::Tuple(items) => {
+                for item in items {
+                    self.target(item, span);
+                }
+            }
+        }
+    }
+
+    /// Collects the names a pattern binds, checking that the alternatives of
+    /// an OR pattern bind the same ones.
+    fn pattern(&mut self, pattern: &Pattern, span: Span, names: &mut Vec<String>) {
+        match pattern {
+            Pattern::Literal(_) | Pattern::Value(_) | Pattern::Wildcard | Pattern::Star(None) => {}
+            Pattern::Capture(name) | Pattern::Star(Some(name)) => names.push(name.clone()),
+            Pattern::Sequence(items) => {
+                for item in items {
+                    self.pattern(item, span, names);
+                }
+            }
+            Pattern::Mapping { entries, rest } => {
+                for (_, value) in entries {
+                    self.pattern(value, span, names);
+                }
+                names.extend(rest.clone());
+            }
+            Pattern::Class { positional, keywords, .. } => {
+                for item in positional.iter().chain(keywords.iter().map(|(_, item)| item)) {
+                    self.pattern(item, span, names);
+                }
+            }
+            Pattern::Or(alternatives) => {
+                let mut bound: Option<Vec<String>> = None;
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i + 1 < alternatives.len()
+                        && let Some(irrefutable) = irrefutable(alternative)
+                    {
+                        self.error(span, &format!("{} makes remaining patterns unreachable", irrefutable));
+                    }
+                    let mut alternative_names = Vec::new();
+                    self.pattern(alternative, span, &mut alternative_names);
+                    alternative_names.sort();
+                    match &bound {
+                        Some(first) if *first != alternative_names => {
+                            self.error(span, "alternative patterns bind different names");
+                        }
+                        Some(_) => {}
+                        None => bound = Some(alternative_names),
+                    }
+                }
+                names.extend(bound.unwrap_or_default());
+            }
+            Pattern::As { pattern, name } => {
+                self.pattern(pattern, span, names);
+                names.push(name.clone());
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression, span: Span) {
+        match expression {
+            Expression::Identifier(_)
+            | Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::Imaginary(_)
+            | Expression::String(_) => {}
+            Expression::FormattedString { parts, .. } => self.fstring_parts(parts),
+            Expression::BinaryOp { left, right, .. } => {
+                self.expression(left, span);
+                self.expression(right, span);
+            }
+            Expression::Compare { left, comparisons } => {
+                self.expression(left, span);
+                for (_, right) in comparisons {
+                    self.expression(right, span);
+                }
+            }
+            Expression::Conditional { test, body, orelse } => {
+                self.expression(body, span);
+                self.expression(test, span);
+                self.expression(orelse, span);
+            }
+            Expression::UnaryOp { operand, .. } => self.expression(operand, span),
+            Expression::MemberAccess { object, .. } => self.expression(object, span),
+            Expression::Call { function, arguments } => {
+                self.expression(function, span);
+                self.arguments(arguments, span);
+            }
+            Expression::List(items) | Expression::Tuple(items) | Expression::Set(items) => {
+                for item in items {
+                    self.expression(item, span);
+                }
+            }
+            Expression::Dict(entries) => {
+                for (key, value) in entries {
+                    self.expression(key, span);
+                    self.expression(value, span);
+                }
+            }
+            Expression::Subscript { value, index } => {
+                self.expression(value, span);
+                self.expression(index, span);
+            }
+            Expression::ListComprehension { element, generators }
+            | Expression::SetComprehension { element, generators }
+            | Expression::Generator { element, generators } => {
+                self.comprehension(&[element], generators, span);
+            }
+            Expression::DictComprehension { key, value, generators } => {
+                self.comprehension(&[key, value], generators, span);
+            }
+            Expression::Yield { value } => {
+                self.yield_expression(span);
+                if let Some(value) = value {
+                    self.expression(value, span);
+                }
+            }
+            Expression::YieldFrom { value } => {
+                self.yield_expression(span);
+                if self.in_async_function() {
+                    self.error(span, "`yield from` inside async function");
+                }
+                self.expression(value, span);
+            }
+            Expression::Walrus { value, .. } => self.expression(value, span),
+            Expression::Await { value } => {
+                self.require_async(span, "`await`");
+                self.expression(value, span);
+            }
+            Expression::Lambda { parameters, body } => {
+                self.parameters(parameters, span);
+                self.function(false, |checker| match body {
+                    LambdaBody::Expression(body) => checker.expression(body, span),
+                    LambdaBody::Block(block) => checker.block(block),
+                });
+            }
+            Expression::Slice { lower, upper, step } => {
+                for bound in [lower, upper, step].into_iter().flatten() {
+                    self.expression(bound, span);
+                }
+            }
+        }
+    }
+
+    fn yield_expression(&mut self, span: Span) {
+        match self.scopes.last_mut() {
+            Some(Scope::Function(function)) => function.is_generator = true,
+            Some(Scope::Comprehension) => self.error(span, "`yield` inside a comprehension"),
+            _ => self.error(span, "`yield` outside function"),
+        }
+    }
+
+    /// Whether the innermost enclosing function is `async`. Comprehensions
+    /// inside one may await too.
+    fn in_async_function(&self) -> bool {
+        let scope = self.scopes.iter().rev().find(|scope| !matches!(scope, Scope::Comprehension));
+        matches!(scope, Some(Scope::Function(FunctionScope { is_async: true, .. })))
+    }
+
+    fn require_async(&mut self, span: Span, construct: &str) {
+        if !self.in_async_function() {
+            self.error(span, &format!("{} outside async function", construct));
+        }
+    }
+
+    /// The first iterable is evaluated in the enclosing scope; the rest of a
+    /// comprehension runs in a scope of its own.
+    fn comprehension(&mut self, elements: &[&Expression], generators: &[Comprehension], span: Span) {
+        if let Some(first) = generators.first() {
+            self.expression(&first.iterable, span);
+        }
+        self.scopes.push(Scope::Comprehension);
+        for (i, generator) in generators.iter().enumerate() {
+            self.target(&generator.target, span);
+            if i > 0 {
+                self.expression(&generator.iterable, span);
+            }
+            for condition in &generator.conditions {
+                self.expression(condition, span);
+            }
+        }
+        for element in elements {
+            self.expression(element, span);
+        }
+        self.scopes.pop();
+    }
+
+    fn fstring_parts(&mut self, parts: &[FStringPart]) {
+        for part in parts {
+            if let FStringPart::Replacement { expression, format_spec, span, .. } = part {
+                self.expression(expression, *span);
+                self.fstring_parts(format_spec);
+            }
+        }
+    }
+}
+
+/// Describes a pattern that matches every subject, such as `_` or a bare name.
+fn irrefutable(pattern: &Pattern) -> Option<String> {
+    match pattern {
+        Pattern::Wildcard => Some("wildcard".to_string()),
+        Pattern::Capture(name) => Some(format!("name capture `{}`", name)),
+        Pattern::As { pattern, .. } => irrefutable(pattern),
+        // Earlier irrefutable alternatives are reported on their own.
+        Pattern::Or(alternatives) => alternatives.last().and_then(irrefutable),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_bython_code;
+
+    /// The diagnostics for a program on one line each, without source snippets.
+    fn diagnostics(code: &str) -> Vec<String> {
+        let source = SourceFile::new("test.bython", code);
+        let parsed = parse_bython_code(&source);
+        assert!(parsed.diagnostics.is_empty(), "`{}` did not parse", code);
+        check_program(&parsed.program, &source).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn return_placement() {
+        assert_eq!(diagnostics("return 1;"), ["test.bython:1:1: error: `return` outside function"]);
+        assert_eq!(
+            diagnostics("class C {\n    return;\n}"),
+            ["test.bython:2:5: error: `return` outside function"],
+        );
+        assert_eq!(
+            diagnostics("fn f() {\n    class C {\n        return;\n    }\n}"),
+            ["test.bython:3:9: error: `return` outside function"],
+        );
+        assert!(diagnostics("fn f() {\n    if x {\n        return 1;\n    }\n}").is_empty());
+        assert!(diagnostics("f = () => { return 1; };").is_empty());
+    }
+
+    #[test]
+    fn yield_placement() {
+        assert_eq!(diagnostics("yield 1;"), ["test.bython:1:1: error: `yield` outside function"]);
+        assert_eq!(diagnostics("x = yield from xs;"), ["test.bython:1:1: error: `yield` outside function"]);
+        assert_eq!(
+            diagnostics("class C {\n    x = yield;\n}"),
+            ["test.bython:2:5: error: `yield` outside function"],
+        );
+        assert_eq!(
+            diagnostics("fn f() {\n    return [(yield x) for x in xs];\n}"),
+            ["test.bython:2:5: error: `yield` inside a comprehension"],
+        );
+        // The first iterable is evaluated in the function itself.
+        assert!(diagnostics("fn f() {\n    xs = [x for x in (yield)];\n}").is_empty());
+        assert!(diagnostics("f = lambda: (yield);").is_empty());
+    }
+
+    #[test]
+    fn return_value_in_generator() {
+        assert_eq!(
+            diagnostics("fn f() {\n    yield 1;\n    return 2;\n}"),
+            ["test.bython:3:5: warning: `return` with a value inside a generator: `for` loops discard the value, \
+              only `yield from` and `StopIteration.value` see it"],
+        );
+        // The `yield` may come after the `return`.
+        assert_eq!(diagnostics("fn f() {\n    return 2;\n    yield 1;\n}").len(), 1);
+        assert!(diagnostics("fn f() {\n    yield 1;\n    return;\n}").is_empty());
+        // A nested function's `yield` does not make the outer one a generator.
+        assert!(diagnostics("fn f() {\n    fn g() {\n        yield 1;\n    }\n    return g;\n}").is_empty());
+    }
+
+    #[test]
+    fn return_value_in_async_generator() {
+        assert_eq!(
+            diagnostics("async fn f() {\n    yield 1;\n    return 2;\n}"),
+            ["test.bython:3:5: error: `return` with a value inside an async generator"],
+        );
+        assert!(diagnostics("async fn f() {\n    yield 1;\n    return;\n}").is_empty());
+        assert!(diagnostics("async fn f() {\n    return 2;\n}").is_empty());
+    }
+
+    #[test]
+    fn await_placement() {
+        assert_eq!(diagnostics("x = await f();"), ["test.bython:1:1: error: `await` outside async function"]);
+        assert_eq!(diagnostics("fn f() { await g(); }"), ["test.bython:1:10: error: `await` outside async function"]);
+        assert!(diagnostics("async fn f() { x = await g(); }").is_empty());
+        // Nested functions, lambdas and classes are not async themselves.
+        assert_eq!(
+            diagnostics("async fn f() { fn g() { await h(); } }"),
+            ["test.bython:1:25: error: `await` outside async function"],
+        );
+        assert_eq!(
+            diagnostics("async fn f() { g = () => { await h(); }; }"),
+            ["test.bython:1:28: error: `await` outside async function"],
+        );
+        assert_eq!(
+            diagnostics("async fn f() { class C { x = await g(); } }"),
+            ["test.bython:1:26: error: `await` outside async function"],
+        );
+        // Comprehensions inside an async function may await.
+        assert!(diagnostics("async fn f() { xs = [await g(x) for x in ys]; }").is_empty());
+        assert_eq!(
+            diagnostics("fn f() { xs = [await g(x) for x in ys]; }"),
+            ["test.bython:1:10: error: `await` outside async function"],
+        );
+    }
+
+    #[test]
+    fn async_statement_placement() {
+        assert!(diagnostics("async fn f() { async for x in xs {} async with a as b {} }").is_empty());
+        assert_eq!(
+            diagnostics("fn f() { async for x in xs {} }"),
+            ["test.bython:1:10: error: `async for` outside async function"],
+        );
+        assert_eq!(
+            diagnostics("async with a {}"),
+            ["test.bython:1:1: error: `async with` outside async function"],
+        );
+        assert_eq!(
+            diagnostics("async fn f() { yield from xs; }"),
+            ["test.bython:1:16: error: `yield from` inside async function"],
+        );
+    }
+
+    #[test]
+    fn irrefutable_patterns() {
+        assert_eq!(
+            diagnostics("match x { case y => {} case 1 => {} }"),
+            ["test.bython:1:11: error: name capture `y` makes remaining patterns unreachable"],
+        );
+        assert_eq!(
+            diagnostics("match x { case _ => {} case 1 => {} }"),
+            ["test.bython:1:11: error: wildcard makes remaining patterns unreachable"],
+        );
+        assert_eq!(
+            diagnostics("match x { case (y as z) => {} case 1 => {} }"),
+            ["test.bython:1:11: error: name capture `y` makes remaining patterns unreachable"],
+        );
+        assert_eq!(
+            diagnostics("match x { case y | 1 => {} }"),
+            [
+                "test.bython:1:11: error: name capture `y` makes remaining patterns unreachable",
+                "test.bython:1:11: error: alternative patterns bind different names",
+            ],
+        );
+        // A guard can reject the subject, and the last case may match anything.
+        assert!(diagnostics("match x { case y if y => {} case 1 => {} case _ => {} }").is_empty());
+    }
+
+    #[test]
+    fn duplicate_captures() {
+        assert_eq!(
+            diagnostics("match x { case [a, a] => {} }"),
+            ["test.bython:1:11: error: multiple assignments to name `a` in pattern"],
+        );
+        assert_eq!(
+            diagnostics("match x { case 1 => {}\n    case {\"k\": a, **a} => {} }"),
+            ["test.bython:2:5: error: multiple assignments to name `a` in pattern"],
+        );
+        assert_eq!(
+            diagnostics("match x { case P(a, b=a) => {} }"),
+            ["test.bython:1:11: error: multiple assignments to name `a` in pattern"],
+        );
+        assert_eq!(
+            diagnostics("match x { case ([a, *b] | (a, *b)) as a => {} }"),
+            ["test.bython:1:11: error: multiple assignments to name `a` in pattern"],
+        );
+    }
+
+    #[test]
+    fn or_pattern_bindings() {
+        assert_eq!(
+            diagnostics("match x { case [a, 1] | [1, b] => {} }"),
+            ["test.bython:1:11: error: alternative patterns bind different names"],
+        );
+        assert_eq!(
+            diagnostics("match x { case [a, b] | P(a) => {} }"),
+            ["test.bython:1:11: error: alternative patterns bind different names"],
+        );
+        // The names may be bound in any order.
+        assert!(diagnostics("match x { case [a, b] | P(b, a) => {} }").is_empty());
+        assert!(diagnostics("match x { case [a, 1] | [1, a] => {} case y => {} }").is_empty());
+    }
+
+    #[test]
+    fn nonlocal_placement() {
+        assert_eq!(diagnostics("nonlocal x;"), ["test.bython:1:1: error: `nonlocal` outside nested function"]);
+        assert_eq!(
+            diagnostics("fn f() { nonlocal x; }"),
+            ["test.bython:1:10: error: `nonlocal` outside nested function"],
+        );
+        assert!(diagnostics("fn f() { fn g() { nonlocal x; } }").is_empty());
+        assert!(diagnostics("fn f() { g = () => { nonlocal x; }; }").is_empty());
+        // A class body inside a function can see the function's variables.
+        assert!(diagnostics("fn f() { class C { nonlocal x; } }").is_empty());
+    }
+
+    #[test]
+    fn break_and_continue_placement() {
+        assert_eq!(diagnostics("break;"), ["test.bython:1:1: error: `break` outside loop"]);
+        assert_eq!(diagnostics("if x { continue; }"), ["test.bython:1:8: error: `continue` outside loop"]);
+        assert!(diagnostics("for x in xs { if x { break; } else { continue; } }").is_empty());
+        assert!(diagnostics("while x { try { break; } finally { continue; } }").is_empty());
+        // Loops do not reach into the functions and classes defined in them,
+        // nor into their own `else` blocks.
+        assert_eq!(
+            diagnostics("while x { fn f() { continue; } }"),
+            ["test.bython:1:20: error: `continue` outside loop"],
+        );
+        assert_eq!(diagnostics("while x { class C { break; } }"), ["test.bython:1:21: error: `break` outside loop"]);
+        assert_eq!(
+            diagnostics("for x in xs {} else { break; }"),
+            ["test.bython:1:23: error: `break` outside loop"],
+        );
+    }
+}